@@ -0,0 +1,187 @@
+use elinalgebra::{F32x2, F32x3};
+
+use crate::ImuSensor;
+
+/// Fuses integrated gyro rates with accelerometer roll & pitch. Yaw has no absolute
+/// reference and is integrated from the gyro alone.
+pub struct ComplementaryFilter {
+    /// Weight given to the integrated gyro angle, in [0,1]
+    alpha: f32,
+    /// Rate at which the gyro bias follows the accelerometer (1/s)
+    bias_gain: f32,
+    /// Roll, pitch & yaw (deg)
+    angles: F32x3,
+    /// Estimated gyro bias (deg/s)
+    gyro_bias: F32x3,
+    initialized: bool,
+}
+
+impl ComplementaryFilter {
+    /// REQUIRES: alpha in [0,1]
+    /// EFFECTS: Returns a filter which trusts the gyro by alpha and the accelerometer by
+    ///          1 - alpha. Gyro bias tracking is disabled.
+    pub fn new(alpha: f32) -> Self {
+        Self {
+            alpha,
+            bias_gain: 0.0,
+            angles: F32x3::filled(0.0),
+            gyro_bias: F32x3::filled(0.0),
+            initialized: false,
+        }
+    }
+
+    /// REQUIRES: alpha in [0,1]
+    /// EFFECTS: Sets the gyro blend coefficient
+    pub fn set_alpha(&mut self, alpha: f32) {
+        self.alpha = alpha;
+    }
+
+    /// EFFECTS: Sets how quickly the roll & pitch gyro bias converges on the
+    ///          accelerometer. Zero disables bias tracking.
+    pub fn set_bias_gain(&mut self, bias_gain: f32) {
+        self.bias_gain = bias_gain;
+    }
+
+    /// EFFECTS: Clears the estimate and bias. The next update re-seeds roll & pitch
+    ///          from the accelerometer.
+    pub fn reset(&mut self) {
+        self.angles = F32x3::filled(0.0);
+        self.gyro_bias = F32x3::filled(0.0);
+        self.initialized = false;
+    }
+
+    /// EFFECTS: Updates the estimate with gyro rates (deg/s) and accelerometer roll &
+    ///          pitch (deg) sampled dt seconds after the previous update. Returns the
+    ///          new roll, pitch & yaw (deg).
+    pub fn update(&mut self, gyro: &F32x3, acc_angle: &F32x2, dt: f32) -> F32x3 {
+        if !self.initialized {
+            self.angles = F32x3::new(acc_angle.x, acc_angle.y, 0.0);
+            self.initialized = true;
+            return self.angles;
+        }
+        let predicted = self.angles + (*gyro - self.gyro_bias) * dt;
+        let err = *acc_angle - F32x2::new(predicted.x, predicted.y);
+        self.angles = F32x3::new(
+            predicted.x + (1.0 - self.alpha) * err.x,
+            predicted.y + (1.0 - self.alpha) * err.y,
+            predicted.z,
+        );
+        self.gyro_bias.x -= self.bias_gain * err.x * dt;
+        self.gyro_bias.y -= self.bias_gain * err.y * dt;
+        self.angles
+    }
+
+    /// EFFECTS: Reads the sensor and updates the estimate, returning roll, pitch & yaw (deg)
+    pub fn step<S: ImuSensor>(&mut self, sensor: &mut S, dt: f32) -> Result<F32x3, S::Error> {
+        let gyro = sensor.read_gyro()?;
+        let acc_angle = sensor.read_acc_angle()?;
+        Ok(self.update(&gyro, &acc_angle, dt))
+    }
+
+    /// EFFECTS: Returns roll, pitch & yaw (deg)
+    pub fn angles(&self) -> F32x3 {
+        self.angles
+    }
+
+    /// EFFECTS: Returns the estimated gyro bias (deg/s)
+    pub fn gyro_bias(&self) -> F32x3 {
+        self.gyro_bias
+    }
+
+    /// EFFECTS: Returns whether the filter has been seeded by an update
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.01;
+
+    /// Replays a level or constantly rolling IMU with a fixed gyro bias
+    struct SyntheticImu {
+        roll_rate: f32,
+        bias: F32x3,
+        t: f32,
+    }
+
+    impl ImuSensor for SyntheticImu {
+        type Error = ();
+
+        fn read_gyro(&mut self) -> Result<F32x3, ()> {
+            Ok(F32x3::new(self.roll_rate, 0.0, 0.0) + self.bias)
+        }
+
+        fn read_acc(&mut self) -> Result<F32x3, ()> {
+            Ok(F32x3::new(0.0, 0.0, 1.0))
+        }
+
+        fn read_acc_angle(&mut self) -> Result<F32x2, ()> {
+            let angle = F32x2::new(self.roll_rate * self.t, 5.0);
+            self.t += DT;
+            Ok(angle)
+        }
+    }
+
+    #[test]
+    fn first_update_seeds_from_accelerometer() {
+        let mut filter = ComplementaryFilter::new(0.98);
+        let angles = filter.update(&F32x3::new(100.0, 0.0, 0.0), &F32x2::new(10.0, -5.0), DT);
+        assert!(filter.is_initialized());
+        assert_eq!((angles.x, angles.y, angles.z), (10.0, -5.0, 0.0));
+        filter.reset();
+        assert!(!filter.is_initialized());
+    }
+
+    #[test]
+    fn static_trace_converges_and_learns_bias() {
+        let mut imu = SyntheticImu {
+            roll_rate: 0.0,
+            bias: F32x3::new(2.0, -1.0, 0.0),
+            t: 0.0,
+        };
+        let mut filter = ComplementaryFilter::new(0.98);
+        filter.set_bias_gain(0.5);
+        for _ in 0..3000 {
+            filter.step(&mut imu, DT).unwrap();
+        }
+        let (angles, bias) = (filter.angles(), filter.gyro_bias());
+        assert!(angles.x.abs() < 0.1, "{:?}", angles);
+        assert!((angles.y - 5.0).abs() < 0.1, "{:?}", angles);
+        assert!((bias.x - 2.0).abs() < 0.1, "{:?}", bias);
+        assert!((bias.y + 1.0).abs() < 0.1, "{:?}", bias);
+    }
+
+    #[test]
+    fn constant_rate_trace_tracks_rotation() {
+        let mut imu = SyntheticImu {
+            roll_rate: 30.0,
+            bias: F32x3::filled(0.0),
+            t: 0.0,
+        };
+        let mut filter = ComplementaryFilter::new(0.98);
+        for _ in 0..200 {
+            filter.step(&mut imu, DT).unwrap();
+        }
+        let expected = 30.0 * (imu.t - DT);
+        assert!(
+            (filter.angles().x - expected).abs() < 0.1,
+            "{:?}",
+            filter.angles()
+        );
+        assert!((filter.angles().y - 5.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn yaw_integrates_gyro() {
+        let mut filter = ComplementaryFilter::new(0.98);
+        let level = F32x2::new(0.0, 0.0);
+        filter.update(&F32x3::filled(0.0), &level, DT);
+        for _ in 0..100 {
+            filter.update(&F32x3::new(0.0, 0.0, 45.0), &level, DT);
+        }
+        assert!((filter.angles().z - 45.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,327 @@
+use core::f32::consts::PI;
+
+use elinalgebra::{F32Quat, F32x3, Matrix};
+
+use crate::{quat_to_angles, ImuSensor};
+
+/// Number of states: orientation quaternion (w, x, y, z) followed by gyro bias (x, y, z)
+pub const EKF_STATES: usize = 7;
+
+type StateMatrix = Matrix<f32, EKF_STATES, EKF_STATES>;
+
+/// Noise parameters for the attitude EKF. Variances are in radians.
+#[derive(Debug, Copy, Clone)]
+pub struct EkfNoise {
+    /// Gyro rate noise variance ((rad/s)^2)
+    pub gyro: f32,
+    /// Gyro bias random walk variance ((rad/s)^2 per second)
+    pub gyro_bias: f32,
+    /// Normalized accelerometer noise variance
+    pub acc: f32,
+    /// Initial quaternion component variance
+    pub initial_attitude: f32,
+    /// Initial gyro bias variance ((rad/s)^2)
+    pub initial_gyro_bias: f32,
+}
+
+impl Default for EkfNoise {
+    fn default() -> Self {
+        Self {
+            gyro: 1e-5,
+            gyro_bias: 1e-9,
+            acc: 1e-2,
+            initial_attitude: 1e-1,
+            initial_gyro_bias: 1e-3,
+        }
+    }
+}
+
+/// Extended kalman filter estimating orientation and gyro bias. Gyro rates drive the
+/// prediction and the accelerometer, assumed to measure only gravity, corrects it.
+/// Yaw and z gyro bias are unobservable without a heading reference, so their
+/// variances grow without bound.
+pub struct AttitudeEkf {
+    noise: EkfNoise,
+    /// State vector: quaternion (w, x, y, z) then gyro bias (rad/s)
+    x: [f32; EKF_STATES],
+    /// State covariance
+    p: StateMatrix,
+    /// Last measurement residual
+    innovation: F32x3,
+}
+
+impl AttitudeEkf {
+    /// EFFECTS: Returns a filter at identity orientation with zero bias
+    pub fn new(noise: EkfNoise) -> Self {
+        let mut ekf = Self {
+            noise,
+            x: [0.0; EKF_STATES],
+            p: StateMatrix::zeros(),
+            innovation: F32x3::filled(0.0),
+        };
+        ekf.reset();
+        ekf
+    }
+
+    /// EFFECTS: Sets the noise parameters. Initial variances apply from the next reset.
+    pub fn set_noise(&mut self, noise: EkfNoise) {
+        self.noise = noise;
+    }
+
+    /// EFFECTS: Returns the filter to identity orientation, zero bias and the initial
+    ///          covariance
+    pub fn reset(&mut self) {
+        self.x = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let (qv, bv) = (self.noise.initial_attitude, self.noise.initial_gyro_bias);
+        self.p = StateMatrix::from_diagonal(&[qv, qv, qv, qv, bv, bv, bv]);
+        self.innovation = F32x3::filled(0.0);
+    }
+
+    /// EFFECTS: Predicts with gyro rates (deg/s) over dt seconds, then corrects with
+    ///          acceleration (Gs). Returns the new orientation.
+    pub fn step(&mut self, gyro: &F32x3, acc: &F32x3, dt: f32) -> F32Quat {
+        self.predict(gyro, dt);
+        self.correct(acc);
+        self.quaternion()
+    }
+
+    /// EFFECTS: Reads the sensor and steps the filter
+    pub fn step_sensor<S: ImuSensor>(
+        &mut self,
+        sensor: &mut S,
+        dt: f32,
+    ) -> Result<F32Quat, S::Error> {
+        let gyro = sensor.read_gyro()?;
+        let acc = sensor.read_acc()?;
+        Ok(self.step(&gyro, &acc, dt))
+    }
+
+    /// EFFECTS: Propagates the state and covariance with gyro rates (deg/s) over dt seconds
+    pub fn predict(&mut self, gyro: &F32x3, dt: f32) {
+        let [w, x, y, z, bx, by, bz] = self.x;
+        let wx = gyro.x.to_radians() - bx;
+        let wy = gyro.y.to_radians() - by;
+        let wz = gyro.z.to_radians() - bz;
+        let h = 0.5 * dt;
+
+        // q' = q + 0.5 * dt * q * (0, w)
+        let q = F32Quat::new(w, x, y, z);
+        let q = (q + q * F32Quat::new(0.0, wx, wy, wz) * h)
+            .normalized()
+            .unwrap_or_else(F32Quat::identity);
+        self.x[..4].copy_from_slice(&[q.w, q.x, q.y, q.z]);
+
+        // d(q * (0, w)) / dw, used for the bias jacobian and gyro noise mapping
+        let xi = Matrix::new([[-x, -y, -z], [w, -z, y], [z, w, -x], [-y, x, w]]);
+        let mut f = StateMatrix::identity();
+        let omega = [
+            [0.0, -wx, -wy, -wz],
+            [wx, 0.0, wz, -wy],
+            [wy, -wz, 0.0, wx],
+            [wz, wy, -wx, 0.0],
+        ];
+        for r in 0..4 {
+            for c in 0..4 {
+                f[(r, c)] += h * omega[r][c];
+            }
+            for c in 0..3 {
+                f[(r, c + 4)] = -h * xi[(r, c)];
+            }
+        }
+
+        let mut q_noise = StateMatrix::zeros();
+        let q_q = (xi * xi.transpose()) * (h * h * self.noise.gyro);
+        for r in 0..4 {
+            for c in 0..4 {
+                q_noise[(r, c)] = q_q[(r, c)];
+            }
+        }
+        for i in 4..EKF_STATES {
+            q_noise[(i, i)] = self.noise.gyro_bias * dt;
+        }
+
+        self.p = f * self.p * f.transpose() + q_noise;
+        self.symmetrize();
+    }
+
+    /// EFFECTS: Corrects the state with acceleration (Gs). Readings with no magnitude
+    ///          are ignored.
+    pub fn correct(&mut self, acc: &F32x3) {
+        let acc = match acc.normalized() {
+            Some(acc) => acc,
+            None => return,
+        };
+        let [w, x, y, z, ..] = self.x;
+
+        // Predicted gravity direction in the sensor frame and its jacobian
+        let predicted = [
+            2.0 * (x * z - w * y),
+            2.0 * (w * x + y * z),
+            w * w - x * x - y * y + z * z,
+        ];
+        let h_mat: Matrix<f32, 3, EKF_STATES> = Matrix::new([
+            [-2.0 * y, 2.0 * z, -2.0 * w, 2.0 * x, 0.0, 0.0, 0.0],
+            [2.0 * x, 2.0 * w, 2.0 * z, 2.0 * y, 0.0, 0.0, 0.0],
+            [2.0 * w, -2.0 * x, -2.0 * y, 2.0 * z, 0.0, 0.0, 0.0],
+        ]);
+        let r = Matrix::<f32, 3, 3>::identity() * self.noise.acc;
+
+        let ph_t = self.p * h_mat.transpose();
+        let s = h_mat * ph_t + r;
+        let s_inv = match s.inverse() {
+            Ok(s_inv) => s_inv,
+            Err(_) => return,
+        };
+        let k = ph_t * s_inv;
+
+        self.innovation = F32x3::new(
+            acc.x - predicted[0],
+            acc.y - predicted[1],
+            acc.z - predicted[2],
+        );
+        let dx = k.mul_vector(&[self.innovation.x, self.innovation.y, self.innovation.z]);
+        for (v, d) in self.x.iter_mut().zip(dx.iter()) {
+            *v += *d;
+        }
+        let q = self.quaternion();
+        self.x[..4].copy_from_slice(&[q.w, q.x, q.y, q.z]);
+
+        // Joseph form keeps the covariance positive definite
+        let i_kh = StateMatrix::identity() - k * h_mat;
+        self.p = i_kh * self.p * i_kh.transpose() + k * r * k.transpose();
+        self.symmetrize();
+    }
+
+    /// EFFECTS: Returns the orientation
+    pub fn quaternion(&self) -> F32Quat {
+        F32Quat::new(self.x[0], self.x[1], self.x[2], self.x[3])
+            .normalized()
+            .unwrap_or_else(F32Quat::identity)
+    }
+
+    /// EFFECTS: Returns roll, pitch & yaw (deg)
+    pub fn angles(&self) -> F32x3 {
+        quat_to_angles(&self.quaternion())
+    }
+
+    /// EFFECTS: Returns the estimated gyro bias (deg/s)
+    pub fn gyro_bias(&self) -> F32x3 {
+        F32x3::new(self.x[4], self.x[5], self.x[6]) * (180.0 / PI)
+    }
+
+    /// EFFECTS: Returns the full state covariance
+    pub fn covariance(&self) -> StateMatrix {
+        self.p
+    }
+
+    /// EFFECTS: Returns the variance of each gyro bias component ((rad/s)^2)
+    pub fn gyro_bias_variance(&self) -> F32x3 {
+        F32x3::new(self.p[(4, 4)], self.p[(5, 5)], self.p[(6, 6)])
+    }
+
+    /// EFFECTS: Returns the residual between the last normalized accelerometer reading
+    ///          and the predicted gravity direction
+    pub fn innovation(&self) -> F32x3 {
+        self.innovation
+    }
+
+    fn symmetrize(&mut self) {
+        let t = self.p.transpose();
+        self.p = (self.p + t) * 0.5;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.01;
+
+    /// Returns the reading of gravity in the frame of orientation q
+    fn gravity(q: &F32Quat) -> F32x3 {
+        q.conjugate().rotate(&F32x3::new(0.0, 0.0, 1.0))
+    }
+
+    fn assert_tilt(ekf: &AttitudeEkf, expected: &F32x3) {
+        let angles = ekf.angles();
+        assert!(
+            (angles.x - expected.x).abs() < 1.0 && (angles.y - expected.y).abs() < 1.0,
+            "{:?} != {:?}",
+            angles,
+            expected
+        );
+    }
+
+    #[test]
+    fn static_trace_converges_to_tilt() {
+        let truth = F32Quat::from_euler(&F32x3::new(
+            -25.0f32.to_radians(),
+            15.0f32.to_radians(),
+            0.0,
+        ));
+        let mut ekf = AttitudeEkf::new(EkfNoise::default());
+        let initial = ekf.covariance();
+        for _ in 0..1000 {
+            ekf.step(&F32x3::filled(0.0), &gravity(&truth), DT);
+        }
+        assert_tilt(&ekf, &F32x3::new(-25.0, 15.0, 0.0));
+        let innovation = ekf.innovation();
+        assert!(innovation.length() < 1e-3, "{:?}", innovation);
+        // Roll & pitch are observed, so their uncertainty shrinks
+        let p = ekf.covariance();
+        assert!(p[(1, 1)] < initial[(1, 1)] && p[(2, 2)] < initial[(2, 2)]);
+    }
+
+    #[test]
+    fn static_trace_learns_gyro_bias() {
+        let bias = F32x3::new(1.5, -2.0, 0.0);
+        let mut ekf = AttitudeEkf::new(EkfNoise::default());
+        let initial = ekf.gyro_bias_variance();
+        for _ in 0..5000 {
+            ekf.step(&bias, &F32x3::new(0.0, 0.0, 1.0), DT);
+        }
+        let estimate = ekf.gyro_bias();
+        assert!((estimate.x - bias.x).abs() < 0.1, "{:?}", estimate);
+        assert!((estimate.y - bias.y).abs() < 0.1, "{:?}", estimate);
+        assert_tilt(&ekf, &F32x3::filled(0.0));
+        let variance = ekf.gyro_bias_variance();
+        assert!(variance.x < initial.x && variance.y < initial.y);
+        // z bias is unobservable without a heading reference
+        assert!(variance.z > 10.0 * variance.x && variance.z > 10.0 * variance.y);
+    }
+
+    #[test]
+    fn constant_rate_trace_tracks_rotation() {
+        let rate = F32x3::new(-30.0, 10.0, 60.0);
+        let mut truth = F32Quat::identity();
+        let mut ekf = AttitudeEkf::new(EkfNoise::default());
+        for _ in 0..200 {
+            truth.integrate_gyro(&(rate * 1.0f32.to_radians()), DT);
+            ekf.step(&rate, &gravity(&truth), DT);
+        }
+        let (angles, expected) = (ekf.angles(), quat_to_angles(&truth));
+        assert_tilt(&ekf, &expected);
+        assert!(
+            (angles.z - expected.z).abs() < 1.0,
+            "{:?} != {:?}",
+            angles,
+            expected
+        );
+    }
+
+    #[test]
+    fn deterministic() {
+        let run = || {
+            let mut ekf = AttitudeEkf::new(EkfNoise::default());
+            for i in 0..500 {
+                let t = i as f32 * DT;
+                let acc = F32x3::new(0.1 * t, -0.05, 1.0);
+                ekf.step(&F32x3::new(5.0, -3.0, 1.0), &acc, DT);
+            }
+            (ekf.quaternion(), ekf.gyro_bias())
+        };
+        let (a, b) = (run(), run());
+        assert_eq!((a.0.w, a.0.x, a.0.y, a.0.z), (b.0.w, b.0.x, b.0.y, b.0.z));
+        assert_eq!((a.1.x, a.1.y, a.1.z), (b.1.x, b.1.y, b.1.z));
+    }
+}
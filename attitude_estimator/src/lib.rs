@@ -0,0 +1,191 @@
+#![no_std]
+
+//! Platform-agnostic attitude estimators fusing gyroscope and accelerometer readings.
+//! Estimators are generic over [`ImuSensor`] so they can be fed by the Mpu6050 on the
+//! drone or by synthetic data on a host machine.
+
+use core::f32::consts::PI;
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+use elinalgebra::{F32Quat, F32x2, F32x3};
+use mpu6050_driver::{Mpu6050, Mpu6050Error};
+
+pub use complementary::ComplementaryFilter;
+pub use ekf::{AttitudeEkf, EkfNoise, EKF_STATES};
+pub use madgwick::Madgwick;
+pub use mahony::Mahony;
+
+mod complementary;
+mod ekf;
+mod madgwick;
+mod mahony;
+
+/// A source of inertial measurements
+pub trait ImuSensor {
+    type Error;
+    /// EFFECTS: Returns angular rates (deg/s)
+    fn read_gyro(&mut self) -> Result<F32x3, Self::Error>;
+    /// EFFECTS: Returns planar acceleration (Gs)
+    fn read_acc(&mut self) -> Result<F32x3, Self::Error>;
+    /// EFFECTS: Returns roll & pitch calculated from acceleration (deg)
+    fn read_acc_angle(&mut self) -> Result<F32x2, Self::Error>;
+}
+
+impl<T, E> ImuSensor for Mpu6050<T>
+where
+    T: Write<Error = E> + WriteRead<Error = E>,
+{
+    type Error = Mpu6050Error<E>;
+
+    fn read_gyro(&mut self) -> Result<F32x3, Self::Error> {
+        Mpu6050::read_gyro(self)
+    }
+
+    fn read_acc(&mut self) -> Result<F32x3, Self::Error> {
+        Mpu6050::read_acc(self)
+    }
+
+    fn read_acc_angle(&mut self) -> Result<F32x2, Self::Error> {
+        Mpu6050::read_acc_angle(self)
+    }
+}
+
+/// Converts an orientation to roll, pitch & yaw (deg)
+pub(crate) fn quat_to_angles(q: &F32Quat) -> F32x3 {
+    q.to_euler() * (180.0 / PI)
+}
+
+/// Scenarios shared by the estimator tests
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    pub const DT: f32 = 0.01;
+
+    /// Raw-count trace in the MPU6050 register format, see its header for the motion
+    const ROLL_20DEG_TRACE: &str = include_str!("../traces/roll_20deg.csv");
+    /// Accelerometer sensitivity of the trace (LSB/G)
+    const TRACE_ACC_SENSITIVITY: f32 = 16384.0;
+    /// Gyroscope sensitivity of the trace (LSB/(deg/s))
+    const TRACE_GYRO_SENSITIVITY: f32 = 131.0;
+
+    /// An estimator driven by gyro, accelerometer & magnetometer readings
+    pub trait Estimator {
+        fn update(&mut self, gyro: &F32x3, acc: &F32x3, mag: Option<&F32x3>, dt: f32);
+        fn angles(&self) -> F32x3;
+        fn reset(&mut self);
+    }
+
+    impl Estimator for Madgwick {
+        fn update(&mut self, gyro: &F32x3, acc: &F32x3, mag: Option<&F32x3>, dt: f32) {
+            Madgwick::update(self, gyro, acc, mag, dt);
+        }
+
+        fn angles(&self) -> F32x3 {
+            Madgwick::angles(self)
+        }
+
+        fn reset(&mut self) {
+            Madgwick::reset(self)
+        }
+    }
+
+    impl Estimator for Mahony {
+        fn update(&mut self, gyro: &F32x3, acc: &F32x3, mag: Option<&F32x3>, dt: f32) {
+            Mahony::update(self, gyro, acc, mag, dt);
+        }
+
+        fn angles(&self) -> F32x3 {
+            Mahony::angles(self)
+        }
+
+        fn reset(&mut self) {
+            Mahony::reset(self)
+        }
+    }
+
+    /// Returns the reading of earth frame vector v in the frame of orientation q
+    pub fn sensor_frame(q: &F32Quat, v: &F32x3) -> F32x3 {
+        q.conjugate().rotate(v)
+    }
+
+    pub fn assert_angles(angles: &F32x3, expected: &F32x3) {
+        assert_tilt(angles, expected);
+        assert!(
+            (angles.z - expected.z).abs() < 1.0,
+            "{:?} != {:?}",
+            angles,
+            expected
+        );
+    }
+
+    /// Yaw is unobservable from the accelerometer, so only roll & pitch are checked
+    pub fn assert_tilt(angles: &F32x3, expected: &F32x3) {
+        let err = *angles - *expected;
+        assert!(
+            err.x.abs() < 1.0 && err.y.abs() < 1.0,
+            "{:?} != {:?}",
+            angles,
+            expected
+        );
+    }
+
+    pub fn static_trace_converges_to_tilt<F: Estimator>(mut filter: F) {
+        let truth = F32Quat::from_euler(&F32x3::new(
+            30.0f32.to_radians(),
+            -20.0f32.to_radians(),
+            0.0,
+        ));
+        let acc = sensor_frame(&truth, &F32x3::new(0.0, 0.0, 1.0));
+        for _ in 0..2000 {
+            filter.update(&F32x3::filled(0.0), &acc, None, DT);
+        }
+        assert_tilt(&filter.angles(), &F32x3::new(30.0, -20.0, 0.0));
+    }
+
+    pub fn constant_rate_trace_tracks_rotation<F: Estimator>(mut filter: F) {
+        let rate = F32x3::new(20.0, 0.0, 45.0);
+        let mut truth = F32Quat::identity();
+        for _ in 0..200 {
+            truth.integrate_gyro(&(rate * (1.0f32.to_radians())), DT);
+            let acc = sensor_frame(&truth, &F32x3::new(0.0, 0.0, 1.0));
+            filter.update(&rate, &acc, None, DT);
+        }
+        assert_angles(&filter.angles(), &quat_to_angles(&truth));
+    }
+
+    pub fn magnetometer_corrects_heading<F: Estimator>(mut filter: F) {
+        let truth = F32Quat::from_euler(&F32x3::new(0.0, 0.0, 40.0f32.to_radians()));
+        let acc = sensor_frame(&truth, &F32x3::new(0.0, 0.0, 1.0));
+        let mag = sensor_frame(&truth, &F32x3::new(0.5, 0.0, -0.8));
+        for _ in 0..3000 {
+            filter.update(&F32x3::filled(0.0), &acc, Some(&mag), DT);
+        }
+        assert_angles(&filter.angles(), &F32x3::new(0.0, 0.0, 40.0));
+        filter.reset();
+        assert_angles(&filter.angles(), &F32x3::filled(0.0));
+    }
+
+    pub fn zero_acceleration_integrates_gyro_only<F: Estimator>(mut filter: F) {
+        for _ in 0..100 {
+            filter.update(&F32x3::new(0.0, 0.0, 30.0), &F32x3::filled(0.0), None, DT);
+        }
+        assert_angles(&filter.angles(), &F32x3::new(0.0, 0.0, 30.0));
+    }
+
+    /// Replays the noisy, biased 20 deg roll trace; the gyro bias makes yaw drift, so
+    /// only the tilt is checked
+    pub fn roll_trace_settles_at_tilt<F: Estimator>(mut filter: F) {
+        for line in ROLL_20DEG_TRACE.lines().filter(|l| !l.starts_with('#')) {
+            let mut counts = line
+                .split(',')
+                .map(|v| v.trim().parse::<i16>().unwrap() as f32);
+            let mut next = || counts.next().unwrap();
+            let acc = F32x3::new(next(), next(), next()) * (1.0 / TRACE_ACC_SENSITIVITY);
+            let gyro = F32x3::new(next(), next(), next()) * (1.0 / TRACE_GYRO_SENSITIVITY);
+            filter.update(&gyro, &acc, None, DT);
+        }
+        assert_tilt(&filter.angles(), &F32x3::new(20.0, 0.0, 0.0));
+    }
+}
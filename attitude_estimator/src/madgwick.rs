@@ -0,0 +1,167 @@
+use elinalgebra::{F32Quat, F32x2, F32x3, Matrix};
+
+use crate::{quat_to_angles, ImuSensor};
+
+/// Madgwick gradient descent AHRS. Gyro rates are integrated and corrected towards
+/// the orientation that best aligns gravity (and optionally magnetic north) with the
+/// measured accelerometer (and magnetometer) vectors.
+pub struct Madgwick {
+    /// Gradient descent step gain (rad/s)
+    beta: f32,
+    /// Orientation of the sensor frame relative to the earth frame
+    q: F32Quat,
+}
+
+impl Madgwick {
+    /// EFFECTS: Returns a filter at identity orientation with the given gain
+    pub fn new(beta: f32) -> Self {
+        Self {
+            beta,
+            q: F32Quat::identity(),
+        }
+    }
+
+    /// EFFECTS: Sets the gradient descent step gain
+    pub fn set_beta(&mut self, beta: f32) {
+        self.beta = beta;
+    }
+
+    /// EFFECTS: Returns the filter to identity orientation
+    pub fn reset(&mut self) {
+        self.q = F32Quat::identity();
+    }
+
+    /// EFFECTS: Updates the orientation with gyro rates (deg/s), acceleration (Gs) and an
+    ///          optional magnetometer reading (any unit) taken dt seconds after the
+    ///          previous update. Returns the new orientation.
+    pub fn update(&mut self, gyro: &F32x3, acc: &F32x3, mag: Option<&F32x3>, dt: f32) -> F32Quat {
+        let rates = F32x3::new(
+            gyro.x.to_radians(),
+            gyro.y.to_radians(),
+            gyro.z.to_radians(),
+        );
+        let omega = F32Quat::new(0.0, rates.x, rates.y, rates.z);
+        let mut q_dot = self.q * omega * 0.5;
+
+        if let Some(acc) = acc.normalized() {
+            let step = match mag.and_then(F32x3::normalized) {
+                Some(mag) => self.marg_gradient(&acc, &mag),
+                None => self.imu_gradient(&acc),
+            };
+            if let Some(step) = step.normalized() {
+                q_dot += step * -self.beta;
+            }
+        }
+
+        self.q += q_dot * dt;
+        self.q.normalize();
+        self.q
+    }
+
+    /// EFFECTS: Reads the sensor and updates the orientation without a magnetometer
+    pub fn step<S: ImuSensor>(&mut self, sensor: &mut S, dt: f32) -> Result<F32Quat, S::Error> {
+        let gyro = sensor.read_gyro()?;
+        let acc = sensor.read_acc()?;
+        Ok(self.update(&gyro, &acc, None, dt))
+    }
+
+    /// EFFECTS: Returns the orientation
+    pub fn quaternion(&self) -> F32Quat {
+        self.q
+    }
+
+    /// EFFECTS: Returns roll, pitch & yaw (deg)
+    pub fn angles(&self) -> F32x3 {
+        quat_to_angles(&self.q)
+    }
+
+    /// Objective function and jacobian for aligning gravity with the unit acceleration
+    fn gravity_terms(&self, acc: &F32x3) -> ([f32; 3], Matrix<f32, 3, 4>) {
+        let F32Quat { w, x, y, z } = self.q;
+        let f = [
+            2.0 * (x * z - w * y) - acc.x,
+            2.0 * (w * x + y * z) - acc.y,
+            2.0 * (0.5 - x * x - y * y) - acc.z,
+        ];
+        let j = Matrix::new([
+            [-2.0 * y, 2.0 * z, -2.0 * w, 2.0 * x],
+            [2.0 * x, 2.0 * w, 2.0 * z, 2.0 * y],
+            [0.0, -4.0 * x, -4.0 * y, 0.0],
+        ]);
+        (f, j)
+    }
+
+    fn imu_gradient(&self, acc: &F32x3) -> F32Quat {
+        let (f, j) = self.gravity_terms(acc);
+        let [w, x, y, z] = j.transpose().mul_vector(&f);
+        F32Quat::new(w, x, y, z)
+    }
+
+    fn marg_gradient(&self, acc: &F32x3, mag: &F32x3) -> F32Quat {
+        let F32Quat { w, x, y, z } = self.q;
+        // Earth frame magnetic field, rotated into the x-z plane
+        let h = self.q.rotate(mag);
+        let bx = F32x2::new(h.x, h.y).length();
+        let bz = h.z;
+        let f_b = [
+            2.0 * bx * (0.5 - y * y - z * z) + 2.0 * bz * (x * z - w * y) - mag.x,
+            2.0 * bx * (x * y - w * z) + 2.0 * bz * (w * x + y * z) - mag.y,
+            2.0 * bx * (w * y + x * z) + 2.0 * bz * (0.5 - x * x - y * y) - mag.z,
+        ];
+        let j_b = Matrix::new([
+            [
+                -2.0 * bz * y,
+                2.0 * bz * z,
+                -4.0 * bx * y - 2.0 * bz * w,
+                -4.0 * bx * z + 2.0 * bz * x,
+            ],
+            [
+                -2.0 * bx * z + 2.0 * bz * x,
+                2.0 * bx * y + 2.0 * bz * w,
+                2.0 * bx * x + 2.0 * bz * z,
+                -2.0 * bx * w + 2.0 * bz * y,
+            ],
+            [
+                2.0 * bx * y,
+                2.0 * bx * z - 4.0 * bz * x,
+                2.0 * bx * w - 4.0 * bz * y,
+                2.0 * bx * x,
+            ],
+        ]);
+        let (f_g, j_g) = self.gravity_terms(acc);
+        let g = j_g.transpose().mul_vector(&f_g);
+        let b = j_b.transpose().mul_vector(&f_b);
+        F32Quat::new(g[0] + b[0], g[1] + b[1], g[2] + b[2], g[3] + b[3])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn static_trace_converges_to_tilt() {
+        testing::static_trace_converges_to_tilt(Madgwick::new(0.5));
+    }
+
+    #[test]
+    fn constant_rate_trace_tracks_rotation() {
+        testing::constant_rate_trace_tracks_rotation(Madgwick::new(0.5));
+    }
+
+    #[test]
+    fn magnetometer_corrects_heading() {
+        testing::magnetometer_corrects_heading(Madgwick::new(0.5));
+    }
+
+    #[test]
+    fn zero_acceleration_integrates_gyro_only() {
+        testing::zero_acceleration_integrates_gyro_only(Madgwick::new(0.5));
+    }
+
+    #[test]
+    fn roll_trace_settles_at_tilt() {
+        testing::roll_trace_settles_at_tilt(Madgwick::new(0.5));
+    }
+}
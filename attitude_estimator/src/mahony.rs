@@ -0,0 +1,150 @@
+use elinalgebra::{F32Quat, F32x2, F32x3};
+
+use crate::{quat_to_angles, ImuSensor};
+
+/// Mahony nonlinear complementary AHRS. The error between measured and estimated
+/// reference directions is fed back into the gyro rates through a PI controller, whose
+/// integral term also tracks gyro bias.
+pub struct Mahony {
+    /// Proportional feedback gain
+    kp: f32,
+    /// Integral feedback gain
+    ki: f32,
+    /// Integral feedback (rad/s)
+    integral: F32x3,
+    /// Orientation of the sensor frame relative to the earth frame
+    q: F32Quat,
+}
+
+impl Mahony {
+    /// EFFECTS: Returns a filter at identity orientation with the given gains
+    pub fn new(kp: f32, ki: f32) -> Self {
+        Self {
+            kp,
+            ki,
+            integral: F32x3::filled(0.0),
+            q: F32Quat::identity(),
+        }
+    }
+
+    /// EFFECTS: Sets the proportional & integral feedback gains
+    pub fn set_gains(&mut self, kp: f32, ki: f32) {
+        self.kp = kp;
+        self.ki = ki;
+    }
+
+    /// EFFECTS: Returns the filter to identity orientation and clears the integral
+    pub fn reset(&mut self) {
+        self.integral = F32x3::filled(0.0);
+        self.q = F32Quat::identity();
+    }
+
+    /// EFFECTS: Updates the orientation with gyro rates (deg/s), acceleration (Gs) and an
+    ///          optional magnetometer reading (any unit) taken dt seconds after the
+    ///          previous update. Returns the new orientation.
+    pub fn update(&mut self, gyro: &F32x3, acc: &F32x3, mag: Option<&F32x3>, dt: f32) -> F32Quat {
+        let mut rates = F32x3::new(
+            gyro.x.to_radians(),
+            gyro.y.to_radians(),
+            gyro.z.to_radians(),
+        );
+
+        if let Some(acc) = acc.normalized() {
+            let inv = self.q.conjugate();
+            // Estimated gravity direction in the sensor frame
+            let v = inv.rotate(&F32x3::new(0.0, 0.0, 1.0));
+            let mut err = acc.cross(&v);
+            if let Some(mag) = mag.and_then(F32x3::normalized) {
+                let h = self.q.rotate(&mag);
+                let b = F32x3::new(F32x2::new(h.x, h.y).length(), 0.0, h.z);
+                // Estimated magnetic field direction in the sensor frame
+                let w = inv.rotate(&b);
+                err += mag.cross(&w);
+            }
+            if self.ki > 0.0 {
+                self.integral += err * (self.ki * dt);
+                rates += self.integral;
+            } else {
+                self.integral = F32x3::filled(0.0);
+            }
+            rates += err * self.kp;
+        }
+
+        self.q.integrate_gyro(&rates, dt);
+        self.q
+    }
+
+    /// EFFECTS: Reads the sensor and updates the orientation without a magnetometer
+    pub fn step<S: ImuSensor>(&mut self, sensor: &mut S, dt: f32) -> Result<F32Quat, S::Error> {
+        let gyro = sensor.read_gyro()?;
+        let acc = sensor.read_acc()?;
+        Ok(self.update(&gyro, &acc, None, dt))
+    }
+
+    /// EFFECTS: Returns the orientation
+    pub fn quaternion(&self) -> F32Quat {
+        self.q
+    }
+
+    /// EFFECTS: Returns roll, pitch & yaw (deg)
+    pub fn angles(&self) -> F32x3 {
+        quat_to_angles(&self.q)
+    }
+
+    /// EFFECTS: Returns the integral feedback, an estimate of negative gyro bias (rad/s)
+    pub fn integral(&self) -> F32x3 {
+        self.integral
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn static_trace_converges_to_tilt() {
+        testing::static_trace_converges_to_tilt(Mahony::new(2.0, 0.0));
+    }
+
+    #[test]
+    fn constant_rate_trace_tracks_rotation() {
+        testing::constant_rate_trace_tracks_rotation(Mahony::new(2.0, 0.0));
+    }
+
+    #[test]
+    fn magnetometer_corrects_heading() {
+        testing::magnetometer_corrects_heading(Mahony::new(2.0, 0.0));
+    }
+
+    #[test]
+    fn zero_acceleration_integrates_gyro_only() {
+        testing::zero_acceleration_integrates_gyro_only(Mahony::new(2.0, 0.0));
+    }
+
+    #[test]
+    fn roll_trace_settles_at_tilt() {
+        testing::roll_trace_settles_at_tilt(Mahony::new(2.0, 0.0));
+    }
+
+    #[test]
+    fn integral_removes_constant_gyro_bias() {
+        let bias = F32x3::new(1.5, -1.0, 0.8);
+        let truth = F32Quat::from_euler(&F32x3::new(0.0, 0.0, 40.0f32.to_radians()));
+        let acc = testing::sensor_frame(&truth, &F32x3::new(0.0, 0.0, 1.0));
+        let mag = testing::sensor_frame(&truth, &F32x3::new(0.5, 0.0, -0.8));
+        let mut filter = Mahony::new(2.0, 0.5);
+        for _ in 0..6000 {
+            filter.update(&bias, &acc, Some(&mag), testing::DT);
+        }
+        let estimate = filter.integral() * -(1.0f32.to_degrees());
+        let err = estimate - bias;
+        assert!(
+            err.x.abs() < 0.05 && err.y.abs() < 0.05 && err.z.abs() < 0.05,
+            "{:?} != {:?}",
+            estimate,
+            bias
+        );
+        testing::assert_angles(&filter.angles(), &F32x3::new(0.0, 0.0, 40.0));
+    }
+}
@@ -0,0 +1,86 @@
+use embedded_hal::adc::{Channel, OneShot};
+
+/// Largest reading of a 12-bit ADC
+pub const ADC_MAX: u16 = 4095;
+
+/// Samples pack voltage through a divider, and optionally current through an analog
+/// sensor, with a one-shot ADC
+pub struct AdcBattery<VP, CP> {
+    voltage_pin: VP,
+    current_pin: Option<CP>,
+    /// ADC reference voltage (V)
+    reference: f32,
+    /// Pack volts per volt at the pin, i.e. the divider ratio
+    voltage_scale: f32,
+    /// Amps per volt at the current pin
+    current_scale: f32,
+    /// Pin voltage (V) read at zero current
+    current_offset: f32,
+}
+
+impl<VP, CP> AdcBattery<VP, CP> {
+    /// EFFECTS: Returns a sampler for a divider of the given ratio on voltage_pin
+    pub fn new(voltage_pin: VP, reference: f32, voltage_scale: f32) -> Self {
+        Self {
+            voltage_pin,
+            current_pin: None,
+            reference,
+            voltage_scale,
+            current_scale: 0.0,
+            current_offset: 0.0,
+        }
+    }
+
+    /// EFFECTS: Adds a current sensor reading current_scale amps per volt above
+    ///          current_offset
+    pub fn with_current(
+        mut self,
+        current_pin: CP,
+        current_scale: f32,
+        current_offset: f32,
+    ) -> Self {
+        self.current_pin = Some(current_pin);
+        self.current_scale = current_scale;
+        self.current_offset = current_offset;
+        self
+    }
+
+    pub fn set_voltage_scale(&mut self, voltage_scale: f32) {
+        self.voltage_scale = voltage_scale;
+    }
+
+    pub fn set_current_scale(&mut self, current_scale: f32, current_offset: f32) {
+        self.current_scale = current_scale;
+        self.current_offset = current_offset;
+    }
+
+    /// EFFECTS: Returns the pack voltage (V), blocking until the conversion completes
+    pub fn read_voltage<ADC, A, E>(&mut self, adc: &mut A) -> Result<f32, E>
+    where
+        VP: Channel<ADC>,
+        A: OneShot<ADC, u16, VP, Error = E>,
+    {
+        let raw = nb::block!(adc.read(&mut self.voltage_pin))?;
+        Ok(self.pin_volts(raw) * self.voltage_scale)
+    }
+
+    /// EFFECTS: Returns the current draw (A), or None without a current sensor
+    pub fn read_current<ADC, A, E>(&mut self, adc: &mut A) -> Result<Option<f32>, E>
+    where
+        CP: Channel<ADC>,
+        A: OneShot<ADC, u16, CP, Error = E>,
+    {
+        let pin = match self.current_pin.as_mut() {
+            Some(pin) => pin,
+            None => return Ok(None),
+        };
+        let raw = nb::block!(adc.read(pin))?;
+        Ok(Some(
+            (self.pin_volts(raw) - self.current_offset) * self.current_scale,
+        ))
+    }
+
+    fn pin_volts(&self, raw: u16) -> f32 {
+        raw.min(ADC_MAX) as f32 / ADC_MAX as f32 * self.reference
+    }
+}
@@ -0,0 +1,321 @@
+#![no_std]
+
+//! Battery voltage & current monitoring. [`Battery`] is platform-agnostic and fed volts
+//! and amps; [`AdcBattery`] samples them through any embedded-hal one-shot ADC such as
+//! the RP2040's.
+
+pub use adc::{AdcBattery, ADC_MAX};
+
+mod adc;
+
+/// An alarm raised once the pack crosses a threshold
+#[derive(Debug, Copy, Clone, Eq, PartialEq, PartialOrd, Ord)]
+pub enum BatteryEvent {
+    Low,
+    Critical,
+}
+
+/// Thresholds and filtering for the battery monitor. Voltages are per cell.
+#[derive(Debug, Copy, Clone)]
+pub struct BatteryConfig {
+    /// Cell count, or None to detect it once the pack voltage settles
+    pub cells: Option<u8>,
+    /// Voltage (V) of a fully charged cell, used to detect the cell count
+    pub cell_max: f32,
+    /// Cell voltage (V) below which the pack is low
+    pub cell_low: f32,
+    /// Cell voltage (V) below which the pack is critical
+    pub cell_critical: f32,
+    /// Time (s) a threshold must be crossed before raising its alarm
+    pub alarm_delay: f32,
+    /// Cutoff frequency (Hz) of the voltage & current low-pass filters
+    pub filter_hz: f32,
+    /// Cell voltage (V) at which throttle is uncompensated
+    pub sag_reference: f32,
+    /// Largest factor throttle is scaled by to counter sag. One disables compensation.
+    pub max_compensation: f32,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            cells: None,
+            cell_max: 4.35,
+            cell_low: 3.5,
+            cell_critical: 3.3,
+            alarm_delay: 2.0,
+            filter_hz: 1.0,
+            sag_reference: 4.2,
+            max_compensation: 1.3,
+        }
+    }
+}
+
+/// Below this voltage (V) no battery is assumed to be connected
+const MIN_DETECT_VOLTAGE: f32 = 2.0;
+/// Time (s) raw readings must stay within DETECT_TOLERANCE before detecting cells, so a
+/// pack still being plugged in is not mistaken for fewer cells
+const DETECT_SETTLE_TIME: f32 = 0.5;
+/// Largest change (V) in raw readings while settling
+const DETECT_TOLERANCE: f32 = 0.1;
+
+/// Tracks the state of charge of a pack from voltage & current readings.
+/// Alarms latch, since a sagging pack recovers when throttle is cut.
+pub struct Battery {
+    config: BatteryConfig,
+    cells: Option<u8>,
+    voltage: Option<f32>,
+    current: Option<f32>,
+    /// Charge drawn so far (mAh)
+    consumed: f32,
+    alarm: Option<BatteryEvent>,
+    /// Time (s) the low & critical thresholds have been crossed
+    low_time: f32,
+    critical_time: f32,
+    /// Raw reading (V) the pack is settling around before cell detection
+    settle_voltage: f32,
+    /// Time (s) raw readings have stayed near settle_voltage
+    settle_time: f32,
+}
+
+impl Battery {
+    pub fn new(config: BatteryConfig) -> Self {
+        Self {
+            cells: config.cells,
+            config,
+            voltage: None,
+            current: None,
+            consumed: 0.0,
+            alarm: None,
+            low_time: 0.0,
+            critical_time: 0.0,
+            settle_voltage: 0.0,
+            settle_time: 0.0,
+        }
+    }
+
+    pub fn config(&self) -> &BatteryConfig {
+        &self.config
+    }
+
+    /// REQUIRES: dt > 0
+    /// EFFECTS: Filters a voltage (V) and optional current (A) reading taken dt seconds
+    ///          after the last one. Returns an alarm if this update raised one.
+    pub fn update(&mut self, volts: f32, amps: Option<f32>, dt: f32) -> Option<BatteryEvent> {
+        let rc = 1.0 / (2.0 * core::f32::consts::PI * self.config.filter_hz);
+        let alpha = dt / (rc + dt);
+        let voltage = match self.voltage {
+            Some(v) => v + alpha * (volts - v),
+            None => volts,
+        };
+        self.voltage = Some(voltage);
+        if let Some(amps) = amps {
+            let current = match self.current {
+                Some(i) => i + alpha * (amps - i),
+                None => amps,
+            };
+            self.current = Some(current);
+            self.consumed += current * dt * 1000.0 / 3600.0;
+        }
+
+        self.detect(volts, dt);
+        let cell = self.cell_voltage()?;
+        self.low_time = Self::threshold_time(self.low_time, cell < self.config.cell_low, dt);
+        self.critical_time =
+            Self::threshold_time(self.critical_time, cell < self.config.cell_critical, dt);
+
+        let event = if self.critical_time >= self.config.alarm_delay {
+            BatteryEvent::Critical
+        } else if self.low_time >= self.config.alarm_delay {
+            BatteryEvent::Low
+        } else {
+            return None;
+        };
+        if Some(event) > self.alarm {
+            self.alarm = Some(event);
+            return self.alarm;
+        }
+        None
+    }
+
+    /// EFFECTS: Returns the filtered pack voltage (V), or None before the first reading
+    pub fn voltage(&self) -> Option<f32> {
+        self.voltage
+    }
+
+    /// EFFECTS: Returns the filtered voltage per cell (V), or None until the cell count
+    ///          is known
+    pub fn cell_voltage(&self) -> Option<f32> {
+        Some(self.voltage? / self.cells? as f32)
+    }
+
+    pub fn cells(&self) -> Option<u8> {
+        self.cells
+    }
+
+    /// EFFECTS: Returns the filtered current draw (A), or None without a current sensor
+    pub fn current(&self) -> Option<f32> {
+        self.current
+    }
+
+    /// EFFECTS: Returns the charge drawn since the last reset (mAh)
+    pub fn consumed_mah(&self) -> f32 {
+        self.consumed
+    }
+
+    /// EFFECTS: Returns the most severe alarm raised since the last reset
+    pub fn alarm(&self) -> Option<BatteryEvent> {
+        self.alarm
+    }
+
+    pub fn is_low(&self) -> bool {
+        self.alarm.is_some()
+    }
+
+    pub fn is_critical(&self) -> bool {
+        self.alarm == Some(BatteryEvent::Critical)
+    }
+
+    /// EFFECTS: Forgets the filtered readings, consumed charge & alarms, as after a pack
+    ///          swap. The cell count is detected again unless configured.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    /// EFFECTS: Returns throttle in [0,1] scaled up to give the same thrust as at the
+    ///          reference cell voltage, limited to the max compensation
+    pub fn compensate(&self, throttle: f32) -> f32 {
+        let factor = match self.cell_voltage() {
+            Some(cell) if cell > 0.0 => {
+                (self.config.sag_reference / cell).clamp(1.0, self.config.max_compensation.max(1.0))
+            }
+            _ => 1.0,
+        };
+        (throttle * factor).clamp(0.0, 1.0)
+    }
+
+    /// EFFECTS: Returns the fewest cells that could reach volts without exceeding
+    ///          cell_max each
+    fn detect_cells(volts: f32, cell_max: f32) -> u8 {
+        let cells = (volts / cell_max) as u8;
+        if cells as f32 * cell_max < volts {
+            cells + 1
+        } else {
+            cells.max(1)
+        }
+    }
+
+    /// Detects the cell count from raw readings once they settle, and forgets a detected
+    /// count when the pack is unplugged
+    fn detect(&mut self, volts: f32, dt: f32) {
+        if volts <= MIN_DETECT_VOLTAGE {
+            if self.config.cells.is_none() {
+                self.cells = None;
+            }
+            self.settle_time = 0.0;
+            self.low_time = 0.0;
+            self.critical_time = 0.0;
+            return;
+        }
+        if self.cells.is_some() {
+            return;
+        }
+        if (volts - self.settle_voltage).abs() > DETECT_TOLERANCE {
+            self.settle_voltage = volts;
+            self.settle_time = 0.0;
+        } else {
+            self.settle_time += dt;
+        }
+        if self.settle_time >= DETECT_SETTLE_TIME {
+            self.cells = Some(Self::detect_cells(volts, self.config.cell_max));
+            // The filter lags a newly connected pack, so restart it at the pack voltage
+            self.voltage = Some(volts);
+        }
+    }
+
+    fn threshold_time(time: f32, crossed: bool, dt: f32) -> f32 {
+        if crossed {
+            time + dt
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.01;
+
+    /// Feeds volts for secs seconds, returning the last alarm raised
+    fn feed(battery: &mut Battery, volts: f32, secs: f32) -> Option<BatteryEvent> {
+        let mut event = None;
+        for _ in 0..(secs / DT) as usize {
+            event = battery.update(volts, None, DT).or(event);
+        }
+        event
+    }
+
+    #[test]
+    fn detects_cells_once_settled() {
+        let mut battery = Battery::new(BatteryConfig::default());
+        battery.update(16.4, None, DT);
+        assert_eq!(battery.cells(), None);
+        feed(&mut battery, 16.4, 1.0);
+        assert_eq!(battery.cells(), Some(4));
+        assert!((battery.cell_voltage().unwrap() - 4.1).abs() < 1e-3);
+    }
+
+    #[test]
+    fn late_plug_in_ramp_is_not_detected_early() {
+        let mut battery = Battery::new(BatteryConfig::default());
+        feed(&mut battery, 0.0, 1.0);
+        // Contacts bouncing and the divider charging as the pack is plugged in
+        for step in 0..20 {
+            battery.update(2.5 + step as f32 * 0.7, None, DT);
+        }
+        feed(&mut battery, 16.4, 1.0);
+        assert_eq!(battery.cells(), Some(4));
+        assert_eq!(feed(&mut battery, 16.4, 5.0), None);
+    }
+
+    #[test]
+    fn unplugging_forgets_detected_cells() {
+        let mut battery = Battery::new(BatteryConfig::default());
+        feed(&mut battery, 8.2, 1.0);
+        assert_eq!(battery.cells(), Some(2));
+        battery.update(0.0, None, DT);
+        assert_eq!(battery.cells(), None);
+        feed(&mut battery, 16.4, 1.0);
+        assert_eq!(battery.cells(), Some(4));
+    }
+
+    #[test]
+    fn configured_cells_are_kept() {
+        let mut battery = Battery::new(BatteryConfig {
+            cells: Some(3),
+            ..BatteryConfig::default()
+        });
+        battery.update(0.0, None, DT);
+        assert_eq!(battery.cells(), Some(3));
+    }
+
+    #[test]
+    fn alarms_escalate_and_latch() {
+        let mut battery = Battery::new(BatteryConfig::default());
+        feed(&mut battery, 16.4, 1.0);
+        assert_eq!(feed(&mut battery, 13.8, 5.0), Some(BatteryEvent::Low));
+        assert_eq!(feed(&mut battery, 13.0, 5.0), Some(BatteryEvent::Critical));
+        assert_eq!(feed(&mut battery, 16.4, 5.0), None);
+        assert!(battery.is_critical());
+    }
+
+    #[test]
+    fn compensation_scales_with_sag() {
+        let mut battery = Battery::new(BatteryConfig::default());
+        assert_eq!(battery.compensate(0.5), 0.5);
+        feed(&mut battery, 14.0, 1.0);
+        assert!((battery.compensate(0.5) - 0.6).abs() < 1e-3);
+    }
+}
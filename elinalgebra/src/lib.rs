@@ -2,16 +2,23 @@
 
 //! A platform-agnostic linear algebra library focused on embedded enviornments.
 
+pub use matrix::{Matrix, Matrix2, Matrix3, Matrix4, MatrixError};
+pub use quaternion::Quaternion;
 pub use utils::*;
 pub use vector2::Vector2;
 pub use vector3::Vector3;
 
+mod matrix;
+mod quaternion;
 mod utils;
 mod vector2;
 mod vector3;
 
 pub type F32x3 = Vector3<f32>;
 pub type F32x2 = Vector2<f32>;
+pub type F32Quat = Quaternion<f32>;
+pub type F32x3x3 = Matrix3<f32>;
+pub type F32x4x4 = Matrix4<f32>;
 
 pub fn to_vec3<T>(vec2: &Vector2<T>, z: T) -> Vector3<T>
 where
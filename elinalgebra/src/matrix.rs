@@ -0,0 +1,336 @@
+use core::ops::{Add, AddAssign, Index, IndexMut, Mul, Sub, SubAssign};
+
+use crate::Vector3;
+
+/// Pivots no larger than this, relative to the largest element, are treated as zero
+const SINGULAR_EPSILON: f32 = 4.0 * f32::EPSILON;
+
+/// A row-major matrix with R rows and C columns
+#[derive(Copy, Clone, Debug)]
+pub struct Matrix<T, const R: usize, const C: usize> {
+    pub data: [[T; C]; R],
+}
+
+pub type Matrix2<T> = Matrix<T, 2, 2>;
+pub type Matrix3<T> = Matrix<T, 3, 3>;
+pub type Matrix4<T> = Matrix<T, 4, 4>;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MatrixError {
+    /// The matrix has no inverse
+    Singular,
+}
+
+impl<T, const R: usize, const C: usize> Matrix<T, R, C> {
+    pub fn new(data: [[T; C]; R]) -> Self {
+        Self { data }
+    }
+
+    pub fn rows(&self) -> usize {
+        R
+    }
+
+    pub fn cols(&self) -> usize {
+        C
+    }
+}
+
+impl<T: Copy, const R: usize, const C: usize> Matrix<T, R, C> {
+    pub fn filled(v: T) -> Self {
+        Self::new([[v; C]; R])
+    }
+
+    /// Returns the transpose of this matrix
+    pub fn transpose(&self) -> Matrix<T, C, R> {
+        Matrix::new(core::array::from_fn(|c| self.col(c)))
+    }
+
+    pub fn row(&self, r: usize) -> [T; C] {
+        self.data[r]
+    }
+
+    pub fn col(&self, c: usize) -> [T; R] {
+        core::array::from_fn(|r| self.data[r][c])
+    }
+}
+
+impl<T, const R: usize, const C: usize> Matrix<T, R, C>
+where
+    T: Copy + Default + AddAssign<T> + Mul<T, Output = T>,
+{
+    /// Multiplies this matrix by a column vector of length C
+    pub fn mul_vector(&self, v: &[T; C]) -> [T; R] {
+        let mut out = [T::default(); R];
+        for (o, row) in out.iter_mut().zip(self.data.iter()) {
+            for (a, b) in row.iter().zip(v.iter()) {
+                *o += *a * *b;
+            }
+        }
+        out
+    }
+}
+
+impl<const R: usize, const C: usize> Matrix<f32, R, C> {
+    pub fn zeros() -> Self {
+        Self::filled(0.0)
+    }
+}
+
+impl<const N: usize> Matrix<f32, N, N> {
+    pub fn identity() -> Self {
+        Self::from_diagonal(&[1.0; N])
+    }
+
+    pub fn from_diagonal(diag: &[f32; N]) -> Self {
+        let mut m = Self::zeros();
+        for (i, v) in diag.iter().enumerate() {
+            m.data[i][i] = *v;
+        }
+        m
+    }
+
+    pub fn diagonal(&self) -> [f32; N] {
+        let mut diag = [0.0; N];
+        for (i, v) in diag.iter_mut().enumerate() {
+            *v = self.data[i][i];
+        }
+        diag
+    }
+
+    pub fn trace(&self) -> f32 {
+        self.diagonal().iter().sum()
+    }
+
+    /// Calculates the determinant using gaussian elimination with partial pivoting
+    pub fn determinant(&self) -> f32 {
+        let mut m = self.data;
+        let tolerance = self.singular_tolerance();
+        let mut det = 1.0;
+        for col in 0..N {
+            let pivot = Self::pivot_row(&m, col);
+            if m[pivot][col].abs() <= tolerance {
+                return 0.0;
+            }
+            if pivot != col {
+                m.swap(pivot, col);
+                det = -det;
+            }
+            det *= m[col][col];
+            let pivot_row = m[col];
+            for row in m.iter_mut().skip(col + 1) {
+                let factor = row[col] / pivot_row[col];
+                for (v, p) in row.iter_mut().zip(pivot_row.iter()).skip(col) {
+                    *v -= factor * *p;
+                }
+            }
+        }
+        det
+    }
+
+    /// Calculates the inverse using gauss-jordan elimination
+    pub fn inverse(&self) -> Result<Self, MatrixError> {
+        let mut m = self.data;
+        let mut inv = Self::identity().data;
+        let tolerance = self.singular_tolerance();
+        for col in 0..N {
+            let pivot = Self::pivot_row(&m, col);
+            if m[pivot][col].abs() <= tolerance {
+                return Err(MatrixError::Singular);
+            }
+            m.swap(pivot, col);
+            inv.swap(pivot, col);
+            let scale = 1.0 / m[col][col];
+            m[col].iter_mut().for_each(|v| *v *= scale);
+            inv[col].iter_mut().for_each(|v| *v *= scale);
+            let (pivot_row, pivot_inv) = (m[col], inv[col]);
+            for (r, (row, inv_row)) in m.iter_mut().zip(inv.iter_mut()).enumerate() {
+                if r == col {
+                    continue;
+                }
+                let factor = row[col];
+                for (v, p) in row.iter_mut().zip(pivot_row.iter()) {
+                    *v -= factor * *p;
+                }
+                for (v, p) in inv_row.iter_mut().zip(pivot_inv.iter()) {
+                    *v -= factor * *p;
+                }
+            }
+        }
+        Ok(Self::new(inv))
+    }
+
+    /// Returns the pivot magnitude below which this matrix is considered singular
+    fn singular_tolerance(&self) -> f32 {
+        let max = self
+            .data
+            .iter()
+            .flat_map(|row| row.iter())
+            .fold(0.0f32, |max, v| max.max(v.abs()));
+        max * N as f32 * SINGULAR_EPSILON
+    }
+
+    fn pivot_row(m: &[[f32; N]; N], col: usize) -> usize {
+        let mut pivot = col;
+        for r in (col + 1)..N {
+            if m[r][col].abs() > m[pivot][col].abs() {
+                pivot = r;
+            }
+        }
+        pivot
+    }
+}
+
+impl<T, const R: usize, const C: usize> Index<(usize, usize)> for Matrix<T, R, C> {
+    type Output = T;
+    fn index(&self, (r, c): (usize, usize)) -> &Self::Output {
+        &self.data[r][c]
+    }
+}
+
+impl<T, const R: usize, const C: usize> IndexMut<(usize, usize)> for Matrix<T, R, C> {
+    fn index_mut(&mut self, (r, c): (usize, usize)) -> &mut Self::Output {
+        &mut self.data[r][c]
+    }
+}
+
+impl<T, const R: usize, const C: usize, const K: usize> Mul<Matrix<T, C, K>> for Matrix<T, R, C>
+where
+    T: Copy + Default + AddAssign<T> + Mul<T, Output = T>,
+{
+    type Output = Matrix<T, R, K>;
+    fn mul(self, o: Matrix<T, C, K>) -> Self::Output {
+        let mut data = [[T::default(); K]; R];
+        for (r, row) in data.iter_mut().enumerate() {
+            for (k, v) in row.iter_mut().enumerate() {
+                for c in 0..C {
+                    *v += self.data[r][c] * o.data[c][k];
+                }
+            }
+        }
+        Matrix::new(data)
+    }
+}
+
+impl<T> Mul<Vector3<T>> for Matrix<T, 3, 3>
+where
+    T: Copy + Default + AddAssign<T> + Mul<T, Output = T>,
+{
+    type Output = Vector3<T>;
+    fn mul(self, v: Vector3<T>) -> Self::Output {
+        let [x, y, z] = self.mul_vector(&[v.x, v.y, v.z]);
+        Vector3::new(x, y, z)
+    }
+}
+
+impl<T, const R: usize, const C: usize> Mul<f32> for Matrix<T, R, C>
+where
+    T: Copy + Mul<f32, Output = T>,
+{
+    type Output = Self;
+    fn mul(mut self, o: f32) -> Self::Output {
+        for row in self.data.iter_mut() {
+            for v in row.iter_mut() {
+                *v = *v * o;
+            }
+        }
+        self
+    }
+}
+
+impl<T, const R: usize, const C: usize> Add for Matrix<T, R, C>
+where
+    T: Copy + AddAssign<T>,
+{
+    type Output = Self;
+    fn add(mut self, o: Self) -> Self::Output {
+        self += o;
+        self
+    }
+}
+
+impl<T, const R: usize, const C: usize> Sub for Matrix<T, R, C>
+where
+    T: Copy + SubAssign<T>,
+{
+    type Output = Self;
+    fn sub(mut self, o: Self) -> Self::Output {
+        self -= o;
+        self
+    }
+}
+
+impl<T, const R: usize, const C: usize> AddAssign for Matrix<T, R, C>
+where
+    T: Copy + AddAssign<T>,
+{
+    fn add_assign(&mut self, o: Self) {
+        for (row, o_row) in self.data.iter_mut().zip(o.data.iter()) {
+            for (v, o_v) in row.iter_mut().zip(o_row.iter()) {
+                *v += *o_v;
+            }
+        }
+    }
+}
+
+impl<T, const R: usize, const C: usize> SubAssign for Matrix<T, R, C>
+where
+    T: Copy + SubAssign<T>,
+{
+    fn sub_assign(&mut self, o: Self) {
+        for (row, o_row) in self.data.iter_mut().zip(o.data.iter()) {
+            for (v, o_v) in row.iter_mut().zip(o_row.iter()) {
+                *v -= *o_v;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transpose_and_col() {
+        let m = Matrix::new([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        assert_eq!(m.transpose().data, [[1.0, 4.0], [2.0, 5.0], [3.0, 6.0]]);
+        assert_eq!(m.col(1), [2.0, 5.0]);
+    }
+
+    #[test]
+    fn empty_dimensions() {
+        let m: Matrix<f32, 0, 3> = Matrix::new([]);
+        assert_eq!(m.transpose().data, [[0.0f32; 0]; 3]);
+        assert_eq!(m.col(2), [0.0f32; 0]);
+        let m: Matrix<f32, 2, 0> = Matrix::filled(0.0);
+        assert_eq!(m.transpose().data.len(), 0);
+        let m: Matrix<f32, 0, 0> = Matrix::new([]);
+        assert_eq!(m.determinant(), 1.0);
+        assert!(m.inverse().is_ok());
+    }
+
+    #[test]
+    fn inverse_round_trip() {
+        let m = Matrix3::new([[4.0, 7.0, 2.0], [3.0, 6.0, 1.0], [2.0, 5.0, 3.0]]);
+        assert!((m.determinant() - 9.0).abs() < 1e-4);
+        let product = m * m.inverse().unwrap();
+        for (row, id_row) in product.data.iter().zip(Matrix3::identity().data.iter()) {
+            for (v, id) in row.iter().zip(id_row.iter()) {
+                assert!((v - id).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn singular_agrees() {
+        // Rows are linearly dependent, but rounding leaves a tiny nonzero pivot
+        let m = Matrix3::new([[0.1, 0.2, 0.3], [0.4, 0.5, 0.6], [0.7, 0.8, 0.9]]);
+        assert_eq!(m.determinant(), 0.0);
+        assert_eq!(m.inverse().unwrap_err(), MatrixError::Singular);
+        assert_eq!(Matrix2::zeros().determinant(), 0.0);
+        assert!(Matrix2::zeros().inverse().is_err());
+        // Small but well conditioned matrices are not singular
+        let small = Matrix2::from_diagonal(&[1e-8, 1e-8]);
+        assert!(small.inverse().is_ok());
+        assert!(small.determinant() > 0.0);
+    }
+}
@@ -0,0 +1,337 @@
+use core::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub};
+
+#[allow(unused_imports)] // inherent f32 methods shadow it in host tests
+use micromath::F32Ext;
+use ufmt::derive::uDebug;
+
+use crate::{precise_sqrt, Vector3};
+
+/// A quaternion of the form w + xi + yj + zk.
+///
+/// Unit quaternions are used to represent orientation. All angles are in radians, and
+/// euler angles follow the aerospace (Z-Y-X) convention with x = roll, y = pitch, z = yaw.
+#[derive(Copy, Clone, Debug, uDebug)]
+pub struct Quaternion<T> {
+    pub w: T,
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+impl<T> Quaternion<T> {
+    pub fn new(w: T, x: T, y: T, z: T) -> Self {
+        Self { w, x, y, z }
+    }
+}
+
+impl<T> Quaternion<T>
+where
+    T: Copy + Neg<Output = T>,
+{
+    /// Returns the conjugate (w, -x, -y, -z)
+    pub fn conjugate(&self) -> Self {
+        Self::new(self.w, -self.x, -self.y, -self.z)
+    }
+}
+
+impl Quaternion<f32> {
+    /// Returns the identity rotation
+    pub fn identity() -> Self {
+        Self::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Builds a rotation of angle radians about axis. Returns identity if axis has no length.
+    pub fn from_axis_angle(axis: &Vector3<f32>, angle: f32) -> Self {
+        let len = precise_sqrt(axis.x * axis.x + axis.y * axis.y + axis.z * axis.z);
+        if len == 0.0 {
+            return Self::identity();
+        }
+        let half = angle * 0.5;
+        let s = half.sin() / len;
+        Self::new(half.cos(), axis.x * s, axis.y * s, axis.z * s)
+    }
+
+    /// Builds a rotation from a rotation vector (axis scaled by angle in radians)
+    pub fn from_rotation_vector(v: &Vector3<f32>) -> Self {
+        let angle = precise_sqrt(v.x * v.x + v.y * v.y + v.z * v.z);
+        Self::from_axis_angle(v, angle)
+    }
+
+    /// Builds a rotation from roll (x), pitch (y) and yaw (z) in radians
+    pub fn from_euler(euler: &Vector3<f32>) -> Self {
+        let (sr, cr) = ((euler.x * 0.5).sin(), (euler.x * 0.5).cos());
+        let (sp, cp) = ((euler.y * 0.5).sin(), (euler.y * 0.5).cos());
+        let (sy, cy) = ((euler.z * 0.5).sin(), (euler.z * 0.5).cos());
+        Self::new(
+            cr * cp * cy + sr * sp * sy,
+            sr * cp * cy - cr * sp * sy,
+            cr * sp * cy + sr * cp * sy,
+            cr * cp * sy - sr * sp * cy,
+        )
+    }
+
+    /// Returns roll (x), pitch (y) and yaw (z) in radians
+    pub fn to_euler(&self) -> Vector3<f32> {
+        let (w, x, y, z) = (self.w, self.x, self.y, self.z);
+        let roll = (2.0 * (w * x + y * z)).atan2(1.0 - 2.0 * (x * x + y * y));
+        let sin_pitch = 2.0 * (w * y - z * x);
+        let pitch = if sin_pitch >= 1.0 {
+            core::f32::consts::FRAC_PI_2
+        } else if sin_pitch <= -1.0 {
+            -core::f32::consts::FRAC_PI_2
+        } else {
+            // atan2 is more accurate than the micromath asin approximation
+            sin_pitch.atan2(precise_sqrt(1.0 - sin_pitch * sin_pitch))
+        };
+        let yaw = (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z));
+        Vector3::new(roll, pitch, yaw)
+    }
+
+    /// Returns the unit rotation axis and angle in radians. A rotation with no angle
+    /// returns the x axis.
+    pub fn to_axis_angle(&self) -> (Vector3<f32>, f32) {
+        let s = precise_sqrt(self.x * self.x + self.y * self.y + self.z * self.z);
+        if s < 1e-6 {
+            (Vector3::new(1.0, 0.0, 0.0), 0.0)
+        } else {
+            let axis = Vector3::new(self.x / s, self.y / s, self.z / s);
+            (axis, 2.0 * s.atan2(self.w))
+        }
+    }
+
+    /// Returns the squared magnitude
+    pub fn norm_squared(&self) -> f32 {
+        self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z
+    }
+
+    /// Returns the magnitude
+    pub fn norm(&self) -> f32 {
+        precise_sqrt(self.norm_squared())
+    }
+
+    /// Returns a unit quaternion in the same direction, or None if the magnitude is zero
+    pub fn normalized(&self) -> Option<Self> {
+        let norm = self.norm();
+        if norm == 0.0 {
+            None
+        } else {
+            Some(*self * (1.0 / norm))
+        }
+    }
+
+    /// Normalizes in place. A zero quaternion is reset to identity.
+    pub fn normalize(&mut self) {
+        *self = self.normalized().unwrap_or_else(Self::identity);
+    }
+
+    /// Returns the multiplicative inverse, or None if the magnitude is zero
+    pub fn inverse(&self) -> Option<Self> {
+        let norm_sq = self.norm_squared();
+        if norm_sq == 0.0 {
+            None
+        } else {
+            Some(self.conjugate() * (1.0 / norm_sq))
+        }
+    }
+
+    /// Rotates v by this quaternion, which must be a unit quaternion
+    pub fn rotate(&self, v: &Vector3<f32>) -> Vector3<f32> {
+        // t = 2 * (q.xyz x v), v' = v + w * t + q.xyz x t
+        let tx = 2.0 * (self.y * v.z - self.z * v.y);
+        let ty = 2.0 * (self.z * v.x - self.x * v.z);
+        let tz = 2.0 * (self.x * v.y - self.y * v.x);
+        Vector3::new(
+            v.x + self.w * tx + (self.y * tz - self.z * ty),
+            v.y + self.w * ty + (self.z * tx - self.x * tz),
+            v.z + self.w * tz + (self.x * ty - self.y * tx),
+        )
+    }
+
+    /// Integrates body-frame angular rates (rad/s) over dt seconds and renormalizes
+    pub fn integrate_gyro(&mut self, rates: &Vector3<f32>, dt: f32) {
+        let omega = Self::new(0.0, rates.x, rates.y, rates.z);
+        let q_dot = *self * omega * 0.5;
+        *self += q_dot * dt;
+        self.normalize();
+    }
+}
+
+impl Default for Quaternion<f32> {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl<T> Mul for Quaternion<T>
+where
+    T: Copy + Add<T, Output = T> + Sub<T, Output = T> + Mul<T, Output = T>,
+{
+    type Output = Self;
+    fn mul(self, o: Self) -> Self::Output {
+        Self {
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+        }
+    }
+}
+
+impl<T> MulAssign for Quaternion<T>
+where
+    T: Copy + Add<T, Output = T> + Sub<T, Output = T> + Mul<T, Output = T>,
+{
+    fn mul_assign(&mut self, o: Self) {
+        *self = *self * o;
+    }
+}
+
+impl<T> Mul<f32> for Quaternion<T>
+where
+    T: Mul<f32, Output = T>,
+{
+    type Output = Self;
+    fn mul(self, o: f32) -> Self::Output {
+        Self {
+            w: self.w * o,
+            x: self.x * o,
+            y: self.y * o,
+            z: self.z * o,
+        }
+    }
+}
+
+impl<T> Add for Quaternion<T>
+where
+    T: Add<T, Output = T>,
+{
+    type Output = Self;
+    fn add(self, o: Self) -> Self::Output {
+        Self {
+            w: self.w + o.w,
+            x: self.x + o.x,
+            y: self.y + o.y,
+            z: self.z + o.z,
+        }
+    }
+}
+
+impl<T> AddAssign for Quaternion<T>
+where
+    T: AddAssign<T>,
+{
+    fn add_assign(&mut self, o: Self) {
+        self.w += o.w;
+        self.x += o.x;
+        self.y += o.y;
+        self.z += o.z;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    const EPS: f32 = 1e-3;
+
+    fn assert_vec_eq(a: &Vector3<f32>, b: &Vector3<f32>) {
+        assert!(
+            (a.x - b.x).abs() < EPS && (a.y - b.y).abs() < EPS && (a.z - b.z).abs() < EPS,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    fn assert_quat_eq(a: &Quaternion<f32>, b: &Quaternion<f32>) {
+        let close = |a: &Quaternion<f32>, b: &Quaternion<f32>| {
+            (a.w - b.w).abs() < EPS
+                && (a.x - b.x).abs() < EPS
+                && (a.y - b.y).abs() < EPS
+                && (a.z - b.z).abs() < EPS
+        };
+        // q & -q are the same rotation
+        assert!(close(a, b) || close(a, &(*b * -1.0)), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn identity_mul() {
+        let q = Quaternion::new(0.5, 0.5, -0.5, 0.5);
+        assert_quat_eq(&(q * Quaternion::identity()), &q);
+        assert_quat_eq(&(Quaternion::identity() * q), &q);
+    }
+
+    #[test]
+    fn conjugate_inverts_unit() {
+        let q = Quaternion::from_axis_angle(&Vector3::new(1.0, 2.0, 3.0), 0.7);
+        assert_quat_eq(&(q * q.conjugate()), &Quaternion::identity());
+        assert_quat_eq(&q.inverse().unwrap(), &q.conjugate());
+        assert!(Quaternion::new(0.0, 0.0, 0.0, 0.0).inverse().is_none());
+    }
+
+    #[test]
+    fn rotate_round_trip() {
+        let q = Quaternion::from_axis_angle(&Vector3::new(0.0, 0.0, 1.0), FRAC_PI_2);
+        let v = Vector3::new(1.0, 0.0, 0.0);
+        let rotated = q.rotate(&v);
+        assert_vec_eq(&rotated, &Vector3::new(0.0, 1.0, 0.0));
+        assert_vec_eq(&q.conjugate().rotate(&rotated), &v);
+    }
+
+    #[test]
+    fn rotate_matches_mul() {
+        let q = Quaternion::from_axis_angle(&Vector3::new(1.0, -1.0, 0.5), 1.1);
+        let v = Vector3::new(0.3, -0.2, 0.9);
+        let p = q * Quaternion::new(0.0, v.x, v.y, v.z) * q.conjugate();
+        assert_vec_eq(&q.rotate(&v), &Vector3::new(p.x, p.y, p.z));
+    }
+
+    #[test]
+    fn axis_angle_round_trip() {
+        let q = Quaternion::from_axis_angle(&Vector3::new(0.0, 3.0, 4.0), 1.2);
+        let (axis, angle) = q.to_axis_angle();
+        assert_vec_eq(&axis, &Vector3::new(0.0, 0.6, 0.8));
+        assert!((angle - 1.2).abs() < EPS);
+        assert_quat_eq(
+            &Quaternion::from_axis_angle(&Vector3::new(0.0, 0.0, 0.0), 1.0),
+            &Quaternion::identity(),
+        );
+    }
+
+    #[test]
+    fn euler_round_trip() {
+        for euler in [
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.3, -0.2, 1.0),
+            Vector3::new(-FRAC_PI_4, 0.5, -2.5),
+        ] {
+            assert_vec_eq(&Quaternion::from_euler(&euler).to_euler(), &euler);
+        }
+    }
+
+    #[test]
+    fn normalize() {
+        let mut q = Quaternion::new(2.0, 0.0, 0.0, 0.0);
+        q.normalize();
+        assert_quat_eq(&q, &Quaternion::identity());
+        assert!(Quaternion::new(0.0, 0.0, 0.0, 0.0).normalized().is_none());
+        let mut zero = Quaternion::new(0.0, 0.0, 0.0, 0.0);
+        zero.normalize();
+        assert_quat_eq(&zero, &Quaternion::identity());
+    }
+
+    #[test]
+    fn integrate_gyro_constant_rate() {
+        let mut q = Quaternion::identity();
+        let rates = Vector3::new(0.0, 0.0, FRAC_PI_2);
+        for _ in 0..1000 {
+            q.integrate_gyro(&rates, 0.001);
+        }
+        assert!((q.norm() - 1.0).abs() < EPS);
+        assert_quat_eq(
+            &q,
+            &Quaternion::from_axis_angle(&Vector3::new(0.0, 0.0, 1.0), FRAC_PI_2),
+        );
+    }
+}
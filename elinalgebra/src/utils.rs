@@ -17,3 +17,29 @@
 // fn i32_to_str(mut i: i32) {
 
 // }
+
+/// Square root refined with two Newton-Raphson iterations over the micromath estimate,
+/// which on its own deviates by up to ~5%. Use when normalizing.
+pub fn precise_sqrt(v: f32) -> f32 {
+    // The micromath estimate of 0 is not 0
+    if v == 0.0 {
+        return 0.0;
+    }
+    let mut root = micromath::F32Ext::sqrt(v);
+    if root.is_nan() {
+        return root;
+    }
+    root = 0.5 * (root + v / root);
+    0.5 * (root + v / root)
+}
+
+/// Clamps v between min and max
+pub fn clamp_component<T: PartialOrd>(v: T, min: T, max: T) -> T {
+    if v < min {
+        min
+    } else if v > max {
+        max
+    } else {
+        v
+    }
+}
@@ -1,7 +1,11 @@
 use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
 
+#[allow(unused_imports)] // inherent f32 methods shadow it in host tests
+use micromath::F32Ext;
 use ufmt::derive::uDebug;
 
+use crate::{clamp_component, precise_sqrt};
+
 #[derive(Copy, Clone, Debug, uDebug)]
 pub struct Vector2<T> {
     pub x: T,
@@ -20,6 +24,123 @@ impl<T: Copy> Vector2<T> {
     }
 }
 
+impl<T> Vector2<T>
+where
+    T: Copy + Add<T, Output = T> + Mul<T, Output = T>,
+{
+    /// Returns the dot product of self and o
+    pub fn dot(&self, o: &Self) -> T {
+        self.x * o.x + self.y * o.y
+    }
+
+    /// Returns the squared length
+    pub fn length_squared(&self) -> T {
+        self.dot(self)
+    }
+}
+
+impl<T> Vector2<T>
+where
+    T: Copy + Sub<T, Output = T> + Mul<T, Output = T>,
+{
+    /// Returns the z component of the 3D cross product of self and o
+    pub fn cross(&self, o: &Self) -> T {
+        self.x * o.y - self.y * o.x
+    }
+}
+
+impl<T> Vector2<T>
+where
+    T: Copy + PartialOrd,
+{
+    /// Clamps each component between the matching components of min and max
+    pub fn clamp(&self, min: &Self, max: &Self) -> Self {
+        Self::new(
+            clamp_component(self.x, min.x, max.x),
+            clamp_component(self.y, min.y, max.y),
+        )
+    }
+
+    /// Returns the smallest component
+    pub fn min_component(&self) -> T {
+        if self.y < self.x {
+            self.y
+        } else {
+            self.x
+        }
+    }
+
+    /// Returns the largest component
+    pub fn max_component(&self) -> T {
+        if self.y > self.x {
+            self.y
+        } else {
+            self.x
+        }
+    }
+}
+
+impl Vector2<f32> {
+    /// Returns the length
+    pub fn length(&self) -> f32 {
+        precise_sqrt(self.length_squared())
+    }
+
+    /// Returns a unit vector in the same direction, or None if the length is zero
+    pub fn normalized(&self) -> Option<Self> {
+        let len = self.length();
+        if len == 0.0 {
+            None
+        } else {
+            Some(*self * (1.0 / len))
+        }
+    }
+
+    /// Normalizes in place. A zero vector is left unchanged.
+    pub fn normalize(&mut self) {
+        if let Some(unit) = self.normalized() {
+            *self = unit;
+        }
+    }
+
+    /// Returns the distance between self and o
+    pub fn distance(&self, o: &Self) -> f32 {
+        (*self - *o).length()
+    }
+
+    /// Linearly interpolates from self to o, where t = 0 is self and t = 1 is o
+    pub fn lerp(&self, o: &Self, t: f32) -> Self {
+        *self + (*o - *self) * t
+    }
+
+    /// Returns the absolute value of each component
+    pub fn abs(&self) -> Self {
+        Self::new(self.x.abs(), self.y.abs())
+    }
+
+    /// Returns the angle between self and o in radians, or None if either has no length
+    pub fn angle_between(&self, o: &Self) -> Option<f32> {
+        let lengths = self.length() * o.length();
+        if lengths == 0.0 {
+            return None;
+        }
+        Some(clamp_component(self.dot(o) / lengths, -1.0, 1.0).acos())
+    }
+}
+
+impl<T> Mul<f32> for Vector2<T>
+where
+    T: Mul<f32, Output = T>,
+{
+    type Output = Self;
+    fn mul(self, o: f32) -> Self::Output {
+        Self {
+            x: self.x * o,
+            y: self.y * o,
+        }
+    }
+}
+
 impl<T> DivAssign<f32> for Vector2<T>
 where
     T: DivAssign<f32>,
@@ -151,3 +272,71 @@ where
         self.y /= other.y;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f32 = 1e-5;
+
+    fn assert_close(a: &Vector2<f32>, b: &Vector2<f32>) {
+        assert!(
+            (a.x - b.x).abs() < EPS && (a.y - b.y).abs() < EPS,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn dot_and_cross() {
+        let (a, b) = (Vector2::new(1.0, 2.0), Vector2::new(3.0, -4.0));
+        assert_eq!(a.dot(&b), -5.0);
+        assert_eq!(a.cross(&b), -10.0);
+        assert_eq!(Vector2::new(1.0, 0.0).cross(&Vector2::new(0.0, 1.0)), 1.0);
+    }
+
+    #[test]
+    fn length_and_distance() {
+        let v = Vector2::new(3.0, -4.0);
+        assert_eq!(v.length_squared(), 25.0);
+        assert!((v.length() - 5.0).abs() < EPS);
+        assert!((v.distance(&Vector2::new(3.0, 0.0)) - 4.0).abs() < EPS);
+    }
+
+    #[test]
+    fn normalized() {
+        let unit = Vector2::new(3.0, 4.0).normalized().unwrap();
+        assert_close(&unit, &Vector2::new(0.6, 0.8));
+        assert!(Vector2::filled(0.0).normalized().is_none());
+        let mut zero = Vector2::filled(0.0);
+        zero.normalize();
+        assert_close(&zero, &Vector2::filled(0.0));
+    }
+
+    #[test]
+    fn lerp() {
+        let (a, b) = (Vector2::new(0.0, 10.0), Vector2::new(4.0, 0.0));
+        assert_close(&a.lerp(&b, 0.5), &Vector2::new(2.0, 5.0));
+    }
+
+    #[test]
+    fn clamp_and_components() {
+        let v = Vector2::new(-2.0, 9.0);
+        let clamped = v.clamp(&Vector2::filled(-1.0), &Vector2::filled(1.0));
+        assert_close(&clamped, &Vector2::new(-1.0, 1.0));
+        assert_eq!(v.min_component(), -2.0);
+        assert_eq!(v.max_component(), 9.0);
+        assert_close(&v.abs(), &Vector2::new(2.0, 9.0));
+    }
+
+    #[test]
+    fn angle_between_parallel_and_antiparallel() {
+        let v = Vector2::new(0.1, 0.7);
+        let parallel = v.angle_between(&(v * 3.0)).unwrap();
+        assert!(!parallel.is_nan() && parallel.abs() < 1e-2, "{}", parallel);
+        let antiparallel = v.angle_between(&(v * -7.0)).unwrap();
+        assert!((antiparallel - core::f32::consts::PI).abs() < 1e-2);
+        assert!(v.angle_between(&Vector2::filled(0.0)).is_none());
+    }
+}
@@ -1,7 +1,11 @@
 use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
 
+#[allow(unused_imports)] // inherent f32 methods shadow it in host tests
+use micromath::F32Ext;
 use ufmt::derive::uDebug;
 
+use crate::{clamp_component, precise_sqrt};
+
 #[derive(Copy, Clone, Debug, uDebug)]
 pub struct Vector3<T> {
     pub x: T,
@@ -21,6 +25,112 @@ impl<T: Copy> Vector3<T> {
     }
 }
 
+impl<T> Vector3<T>
+where
+    T: Copy + Add<T, Output = T> + Sub<T, Output = T> + Mul<T, Output = T>,
+{
+    /// Returns the dot product of self and o
+    pub fn dot(&self, o: &Self) -> T {
+        self.x * o.x + self.y * o.y + self.z * o.z
+    }
+
+    /// Returns the cross product self x o
+    pub fn cross(&self, o: &Self) -> Self {
+        Self::new(
+            self.y * o.z - self.z * o.y,
+            self.z * o.x - self.x * o.z,
+            self.x * o.y - self.y * o.x,
+        )
+    }
+
+    /// Returns the squared length
+    pub fn length_squared(&self) -> T {
+        self.dot(self)
+    }
+}
+
+impl<T> Vector3<T>
+where
+    T: Copy + PartialOrd,
+{
+    /// Clamps each component between the matching components of min and max
+    pub fn clamp(&self, min: &Self, max: &Self) -> Self {
+        Self::new(
+            clamp_component(self.x, min.x, max.x),
+            clamp_component(self.y, min.y, max.y),
+            clamp_component(self.z, min.z, max.z),
+        )
+    }
+
+    /// Returns the smallest component
+    pub fn min_component(&self) -> T {
+        let xy = if self.y < self.x { self.y } else { self.x };
+        if self.z < xy {
+            self.z
+        } else {
+            xy
+        }
+    }
+
+    /// Returns the largest component
+    pub fn max_component(&self) -> T {
+        let xy = if self.y > self.x { self.y } else { self.x };
+        if self.z > xy {
+            self.z
+        } else {
+            xy
+        }
+    }
+}
+
+impl Vector3<f32> {
+    /// Returns the length
+    pub fn length(&self) -> f32 {
+        precise_sqrt(self.length_squared())
+    }
+
+    /// Returns a unit vector in the same direction, or None if the length is zero
+    pub fn normalized(&self) -> Option<Self> {
+        let len = self.length();
+        if len == 0.0 {
+            None
+        } else {
+            Some(*self * (1.0 / len))
+        }
+    }
+
+    /// Normalizes in place. A zero vector is left unchanged.
+    pub fn normalize(&mut self) {
+        if let Some(unit) = self.normalized() {
+            *self = unit;
+        }
+    }
+
+    /// Returns the distance between self and o
+    pub fn distance(&self, o: &Self) -> f32 {
+        (*self - *o).length()
+    }
+
+    /// Linearly interpolates from self to o, where t = 0 is self and t = 1 is o
+    pub fn lerp(&self, o: &Self, t: f32) -> Self {
+        *self + (*o - *self) * t
+    }
+
+    /// Returns the absolute value of each component
+    pub fn abs(&self) -> Self {
+        Self::new(self.x.abs(), self.y.abs(), self.z.abs())
+    }
+
+    /// Returns the angle between self and o in radians, or None if either has no length
+    pub fn angle_between(&self, o: &Self) -> Option<f32> {
+        let lengths = self.length() * o.length();
+        if lengths == 0.0 {
+            return None;
+        }
+        Some(clamp_component(self.dot(o) / lengths, -1.0, 1.0).acos())
+    }
+}
+
 impl<T> Mul<f32> for Vector3<T>
 where
     T: Mul<f32, Output = T>,
@@ -178,3 +288,97 @@ where
         self.z /= other.z;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f32 = 1e-5;
+
+    fn assert_close(a: &Vector3<f32>, b: &Vector3<f32>) {
+        assert!(
+            (a.x - b.x).abs() < EPS && (a.y - b.y).abs() < EPS && (a.z - b.z).abs() < EPS,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn dot_and_cross() {
+        let (a, b) = (Vector3::new(1.0, 2.0, 3.0), Vector3::new(4.0, -5.0, 6.0));
+        assert_eq!(a.dot(&b), 12.0);
+        assert_close(&a.cross(&b), &Vector3::new(27.0, 6.0, -13.0));
+        let x = Vector3::new(1.0, 0.0, 0.0);
+        assert_close(
+            &x.cross(&Vector3::new(0.0, 1.0, 0.0)),
+            &Vector3::new(0.0, 0.0, 1.0),
+        );
+        assert_close(&x.cross(&x), &Vector3::filled(0.0));
+    }
+
+    #[test]
+    fn length_and_distance() {
+        let v = Vector3::new(2.0, 3.0, 6.0);
+        assert_eq!(v.length_squared(), 49.0);
+        assert!((v.length() - 7.0).abs() < EPS);
+        assert!((v.distance(&Vector3::new(2.0, 3.0, 0.0)) - 6.0).abs() < EPS);
+    }
+
+    #[test]
+    fn normalized() {
+        let unit = Vector3::new(0.0, 3.0, 4.0).normalized().unwrap();
+        assert_close(&unit, &Vector3::new(0.0, 0.6, 0.8));
+        assert!(Vector3::filled(0.0).normalized().is_none());
+    }
+
+    #[test]
+    fn normalize_in_place() {
+        let mut v = Vector3::new(0.0, 0.0, -2.0);
+        v.normalize();
+        assert_close(&v, &Vector3::new(0.0, 0.0, -1.0));
+        let mut zero = Vector3::filled(0.0);
+        zero.normalize();
+        assert_close(&zero, &Vector3::filled(0.0));
+    }
+
+    #[test]
+    fn lerp() {
+        let (a, b) = (Vector3::new(0.0, 10.0, -2.0), Vector3::new(4.0, 0.0, 2.0));
+        assert_close(&a.lerp(&b, 0.0), &a);
+        assert_close(&a.lerp(&b, 1.0), &b);
+        assert_close(&a.lerp(&b, 0.25), &Vector3::new(1.0, 7.5, -1.0));
+    }
+
+    #[test]
+    fn clamp_and_components() {
+        let v = Vector3::new(-2.0, 0.5, 9.0);
+        let clamped = v.clamp(&Vector3::filled(-1.0), &Vector3::filled(1.0));
+        assert_close(&clamped, &Vector3::new(-1.0, 0.5, 1.0));
+        assert_eq!(v.min_component(), -2.0);
+        assert_eq!(v.max_component(), 9.0);
+        assert_close(&v.abs(), &Vector3::new(2.0, 0.5, 9.0));
+    }
+
+    #[test]
+    fn angle_between() {
+        let x = Vector3::new(1.0, 0.0, 0.0);
+        let angle = x.angle_between(&Vector3::new(0.0, 0.0, 5.0)).unwrap();
+        assert!((angle - core::f32::consts::FRAC_PI_2).abs() < 1e-3);
+        assert!(x.angle_between(&Vector3::filled(0.0)).is_none());
+    }
+
+    #[test]
+    fn angle_between_parallel_and_antiparallel() {
+        // Rounding pushes the cosine of these just past +-1
+        let v = Vector3::new(0.1, 0.2, 0.3);
+        let parallel = v.angle_between(&(v * 3.0)).unwrap();
+        assert!(!parallel.is_nan() && parallel.abs() < 1e-2, "{}", parallel);
+        let antiparallel = v.angle_between(&(v * -7.0)).unwrap();
+        assert!(
+            (antiparallel - core::f32::consts::PI).abs() < 1e-2,
+            "{}",
+            antiparallel
+        );
+    }
+}
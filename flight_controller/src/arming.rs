@@ -0,0 +1,420 @@
+use elinalgebra::F32x3;
+
+/// Why an arm request was refused
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ArmRefusal {
+    AlreadyArmed,
+    /// Roll or pitch is beyond the level limit
+    NotLevel,
+    /// Throttle is above the arming limit
+    ThrottleHigh,
+    /// Sensors have not finished initialising
+    SensorsNotReady,
+    /// The IMU failed its self-test
+    ImuFailed,
+    /// The command link is not healthy
+    LinkUnhealthy,
+    /// The battery is below its critical voltage
+    BatteryCritical,
+    /// A crash disarmed the motors and has not been cleared by a disarm command
+    CrashLatched,
+}
+
+/// Why the motors were disarmed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DisarmReason {
+    /// An explicit disarm command
+    Command,
+    /// Throttle stayed at idle for the inactivity timeout
+    Inactivity,
+    /// The drone is upside down or tumbling
+    Crash,
+}
+
+/// Whether the motors may spin
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ArmingState {
+    Disarmed,
+    Armed,
+}
+
+/// Thresholds for arming and automatic disarming. Angles are in degrees.
+#[derive(Debug, Copy, Clone)]
+pub struct ArmingConfig {
+    /// Largest roll or pitch (deg) the drone may arm at
+    pub max_arm_angle: f32,
+    /// Largest throttle the drone may arm at
+    pub max_arm_throttle: f32,
+    /// Throttle at or below which the drone is considered idle
+    pub idle_throttle: f32,
+    /// Time (s) the drone may idle while armed before disarming. Zero never disarms.
+    pub inactivity_timeout: f32,
+    /// Roll or pitch (deg) beyond which the drone may have crashed
+    pub crash_angle: f32,
+    /// Rotation rate (deg/s) on any axis beyond which the drone may be tumbling. Must be
+    /// below the gyro's full scale range, where readings saturate. The default suits the
+    /// narrowest common range of 250 deg/s.
+    pub crash_rate: f32,
+    /// Time (s) a crash condition must persist before disarming
+    pub crash_time: f32,
+}
+
+impl Default for ArmingConfig {
+    fn default() -> Self {
+        Self {
+            max_arm_angle: 25.0,
+            max_arm_throttle: 0.05,
+            idle_throttle: 0.02,
+            inactivity_timeout: 5.0,
+            crash_angle: 70.0,
+            crash_rate: 225.0,
+            crash_time: 0.5,
+        }
+    }
+}
+
+/// Sensor and link state checked before arming and while armed
+#[derive(Debug, Copy, Clone)]
+pub struct SafetyInputs {
+    /// Estimated roll, pitch & yaw (deg)
+    pub attitude: F32x3,
+    /// Gyro rates (deg/s)
+    pub gyro: F32x3,
+    /// Commanded throttle in [0,1]
+    pub throttle: f32,
+    pub sensors_ready: bool,
+    /// Whether the IMU passed its self-test
+    pub imu_healthy: bool,
+    pub link_healthy: bool,
+    pub battery_critical: bool,
+}
+
+/// Gates motor output behind an explicit arm command. Arming is refused unless the
+/// preflight checks pass, and the motors disarm themselves after inactivity or a crash.
+pub struct Arming {
+    config: ArmingConfig,
+    state: ArmingState,
+    disarm_reason: Option<DisarmReason>,
+    /// Time (s) the throttle has been idle while armed
+    idle_time: f32,
+    /// Time (s) a crash condition has persisted
+    crash_time: f32,
+}
+
+impl Arming {
+    /// EFFECTS: Returns a disarmed state machine
+    pub fn new(config: ArmingConfig) -> Self {
+        Self {
+            config,
+            state: ArmingState::Disarmed,
+            disarm_reason: None,
+            idle_time: 0.0,
+            crash_time: 0.0,
+        }
+    }
+
+    pub fn config(&self) -> &ArmingConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: ArmingConfig) {
+        self.config = config;
+    }
+
+    pub fn state(&self) -> ArmingState {
+        self.state
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.state == ArmingState::Armed
+    }
+
+    /// EFFECTS: Returns why the motors were last disarmed, or None if they never were
+    pub fn disarm_reason(&self) -> Option<DisarmReason> {
+        self.disarm_reason
+    }
+
+    /// EFFECTS: Returns the first preflight check the inputs fail, if any
+    pub fn check_preflight(&self, inputs: &SafetyInputs) -> Result<(), ArmRefusal> {
+        if !inputs.sensors_ready {
+            Err(ArmRefusal::SensorsNotReady)
+        } else if !inputs.imu_healthy {
+            Err(ArmRefusal::ImuFailed)
+        } else if !inputs.link_healthy {
+            Err(ArmRefusal::LinkUnhealthy)
+        } else if inputs.battery_critical {
+            Err(ArmRefusal::BatteryCritical)
+        } else if inputs.attitude.x.abs() > self.config.max_arm_angle
+            || inputs.attitude.y.abs() > self.config.max_arm_angle
+        {
+            Err(ArmRefusal::NotLevel)
+        } else if inputs.throttle > self.config.max_arm_throttle {
+            Err(ArmRefusal::ThrottleHigh)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// EFFECTS: Arms if disarmed, no crash is latched and the preflight checks pass,
+    ///          otherwise returns why not
+    pub fn arm(&mut self, inputs: &SafetyInputs) -> Result<(), ArmRefusal> {
+        if self.is_armed() {
+            return Err(ArmRefusal::AlreadyArmed);
+        }
+        if self.disarm_reason == Some(DisarmReason::Crash) {
+            return Err(ArmRefusal::CrashLatched);
+        }
+        self.check_preflight(inputs)?;
+        self.state = ArmingState::Armed;
+        self.idle_time = 0.0;
+        self.crash_time = 0.0;
+        Ok(())
+    }
+
+    /// EFFECTS: Disarms on command, which also clears a latched crash
+    pub fn disarm(&mut self) {
+        self.disarm_with(DisarmReason::Command);
+    }
+
+    /// REQUIRES: dt > 0
+    /// EFFECTS: Advances the inactivity and crash timers by dt seconds. Returns the reason
+    ///          if this update disarmed the motors.
+    pub fn update(&mut self, inputs: &SafetyInputs, dt: f32) -> Option<DisarmReason> {
+        if !self.is_armed() {
+            return None;
+        }
+
+        if inputs.throttle <= self.config.idle_throttle {
+            self.idle_time += dt;
+        } else {
+            self.idle_time = 0.0;
+        }
+        if self.config.inactivity_timeout > 0.0 && self.idle_time >= self.config.inactivity_timeout
+        {
+            self.disarm_with(DisarmReason::Inactivity);
+            return self.disarm_reason;
+        }
+
+        let (att, gyro) = (&inputs.attitude, &inputs.gyro);
+        let crashed = att.x.abs() > self.config.crash_angle
+            || att.y.abs() > self.config.crash_angle
+            || gyro.abs().max_component() > self.config.crash_rate;
+        if crashed {
+            self.crash_time += dt;
+        } else {
+            self.crash_time = 0.0;
+        }
+        if crashed && self.crash_time >= self.config.crash_time {
+            self.disarm_with(DisarmReason::Crash);
+            return self.disarm_reason;
+        }
+        None
+    }
+
+    /// EFFECTS: Returns the outputs while armed, otherwise all zeros
+    pub fn gate<const N: usize>(&self, outputs: [f32; N]) -> [f32; N] {
+        if self.is_armed() {
+            outputs
+        } else {
+            [0.0; N]
+        }
+    }
+
+    fn disarm_with(&mut self, reason: DisarmReason) {
+        self.state = ArmingState::Disarmed;
+        self.disarm_reason = Some(reason);
+        self.idle_time = 0.0;
+        self.crash_time = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exact in binary so timers land on their thresholds
+    const DT: f32 = 0.125;
+
+    fn ready() -> SafetyInputs {
+        SafetyInputs {
+            attitude: F32x3::filled(0.0),
+            gyro: F32x3::filled(0.0),
+            throttle: 0.0,
+            sensors_ready: true,
+            imu_healthy: true,
+            link_healthy: true,
+            battery_critical: false,
+        }
+    }
+
+    fn flying() -> SafetyInputs {
+        SafetyInputs {
+            throttle: 0.5,
+            ..ready()
+        }
+    }
+
+    fn armed() -> Arming {
+        let mut arming = Arming::new(ArmingConfig::default());
+        arming.arm(&ready()).unwrap();
+        arming
+    }
+
+    #[test]
+    fn each_failed_check_refuses_to_arm() {
+        let mut arming = Arming::new(ArmingConfig::default());
+        let cases = [
+            (
+                SafetyInputs {
+                    sensors_ready: false,
+                    ..ready()
+                },
+                ArmRefusal::SensorsNotReady,
+            ),
+            (
+                SafetyInputs {
+                    imu_healthy: false,
+                    ..ready()
+                },
+                ArmRefusal::ImuFailed,
+            ),
+            (
+                SafetyInputs {
+                    link_healthy: false,
+                    ..ready()
+                },
+                ArmRefusal::LinkUnhealthy,
+            ),
+            (
+                SafetyInputs {
+                    battery_critical: true,
+                    ..ready()
+                },
+                ArmRefusal::BatteryCritical,
+            ),
+            (
+                SafetyInputs {
+                    attitude: F32x3::new(26.0, 0.0, 0.0),
+                    ..ready()
+                },
+                ArmRefusal::NotLevel,
+            ),
+            (
+                SafetyInputs {
+                    attitude: F32x3::new(0.0, -26.0, 0.0),
+                    ..ready()
+                },
+                ArmRefusal::NotLevel,
+            ),
+            (
+                SafetyInputs {
+                    throttle: 0.06,
+                    ..ready()
+                },
+                ArmRefusal::ThrottleHigh,
+            ),
+        ];
+        for (inputs, refusal) in cases.iter() {
+            assert_eq!(arming.arm(inputs), Err(*refusal));
+            assert!(!arming.is_armed());
+        }
+        // Yaw & limits themselves are allowed
+        let edge = SafetyInputs {
+            attitude: F32x3::new(25.0, -25.0, 170.0),
+            throttle: 0.05,
+            ..ready()
+        };
+        assert_eq!(arming.arm(&edge), Ok(()));
+        assert_eq!(arming.arm(&ready()), Err(ArmRefusal::AlreadyArmed));
+    }
+
+    #[test]
+    fn idle_throttle_disarms_after_timeout() {
+        let mut arming = armed();
+        // 4.875 s of idle, interrupted by thrust, does not disarm
+        for _ in 0..39 {
+            assert_eq!(arming.update(&ready(), DT), None);
+        }
+        assert_eq!(arming.update(&flying(), DT), None);
+        for _ in 0..39 {
+            assert_eq!(arming.update(&ready(), DT), None);
+        }
+        assert!(arming.is_armed());
+        assert_eq!(arming.update(&ready(), DT), Some(DisarmReason::Inactivity));
+        assert!(!arming.is_armed());
+        assert_eq!(arming.disarm_reason(), Some(DisarmReason::Inactivity));
+        // Inactivity is not latched
+        assert_eq!(arming.arm(&ready()), Ok(()));
+    }
+
+    #[test]
+    fn zero_timeout_never_disarms_for_inactivity() {
+        let mut arming = Arming::new(ArmingConfig {
+            inactivity_timeout: 0.0,
+            ..ArmingConfig::default()
+        });
+        arming.arm(&ready()).unwrap();
+        for _ in 0..1000 {
+            assert_eq!(arming.update(&ready(), DT), None);
+        }
+        assert!(arming.is_armed());
+    }
+
+    #[test]
+    fn rate_beyond_crash_rate_disarms_after_crash_time() {
+        let mut arming = armed();
+        let at_limit = SafetyInputs {
+            gyro: F32x3::new(0.0, 0.0, -225.0),
+            ..flying()
+        };
+        for _ in 0..10 {
+            assert_eq!(arming.update(&at_limit, DT), None);
+        }
+        let tumbling = SafetyInputs {
+            gyro: F32x3::new(0.0, 0.0, -226.0),
+            ..flying()
+        };
+        // A brief spike resets once the rate drops
+        for _ in 0..3 {
+            assert_eq!(arming.update(&tumbling, DT), None);
+        }
+        assert_eq!(arming.update(&flying(), DT), None);
+        for _ in 0..3 {
+            assert_eq!(arming.update(&tumbling, DT), None);
+        }
+        assert_eq!(arming.update(&tumbling, DT), Some(DisarmReason::Crash));
+        assert!(!arming.is_armed());
+    }
+
+    #[test]
+    fn crash_stays_latched_until_disarm_command() {
+        let mut arming = armed();
+        let upside_down = SafetyInputs {
+            attitude: F32x3::new(180.0, 0.0, 0.0),
+            ..flying()
+        };
+        for _ in 0..3 {
+            assert_eq!(arming.update(&upside_down, DT), None);
+        }
+        assert_eq!(arming.update(&upside_down, DT), Some(DisarmReason::Crash));
+
+        // Level again, but a crash must be acknowledged before arming
+        assert_eq!(arming.update(&ready(), DT), None);
+        assert_eq!(arming.arm(&ready()), Err(ArmRefusal::CrashLatched));
+        assert_eq!(arming.disarm_reason(), Some(DisarmReason::Crash));
+        arming.disarm();
+        assert_eq!(arming.arm(&ready()), Ok(()));
+    }
+
+    #[test]
+    fn gate_zeroes_outputs_while_disarmed() {
+        let mut arming = Arming::new(ArmingConfig::default());
+        let outputs = [0.2, 0.4, 0.6, 0.8];
+        assert_eq!(arming.gate(outputs), [0.0; 4]);
+        arming.arm(&ready()).unwrap();
+        assert_eq!(arming.gate(outputs), outputs);
+        arming.disarm();
+        assert_eq!(arming.gate(outputs), [0.0; 4]);
+        assert_eq!(arming.disarm_reason(), Some(DisarmReason::Command));
+    }
+}
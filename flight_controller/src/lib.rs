@@ -0,0 +1,13 @@
+#![no_std]
+
+//! Platform-agnostic control loops for flying the drone.
+
+pub use arming::{ArmRefusal, Arming, ArmingConfig, ArmingState, DisarmReason, SafetyInputs};
+pub use mixer::Mixer;
+pub use pid::{Pid, PidGains};
+pub use stabilizer::{AttitudeSetpoint, Axis, AxisDemands, Stabilizer};
+
+mod arming;
+mod mixer;
+mod pid;
+mod stabilizer;
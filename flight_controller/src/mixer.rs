@@ -0,0 +1,265 @@
+use elinalgebra::Matrix;
+
+use crate::AxisDemands;
+
+/// Maps throttle, roll, pitch & yaw demands onto N outputs using a mixing table whose
+/// columns are the throttle, roll, pitch & yaw contribution to each output.
+///
+/// Preset motor orders follow the common flight controller layouts. Presets are scaled
+/// so a demand of 1 on a single axis spans the full motor range. Positive roll raises
+/// the left side, positive pitch raises the rear and positive yaw turns clockwise viewed
+/// from above.
+pub struct Mixer<const N: usize> {
+    table: Matrix<f32, N, 4>,
+    /// Outputs driving servos rather than motors
+    servos: [bool; N],
+    saturated: bool,
+}
+
+impl<const N: usize> Mixer<N> {
+    /// EFFECTS: Returns a mixer using a custom table where every output is a motor
+    pub fn new(table: Matrix<f32, N, 4>) -> Self {
+        Self {
+            table,
+            servos: [false; N],
+            saturated: false,
+        }
+    }
+
+    /// REQUIRES: At least one motor is off each axis
+    /// EFFECTS: Returns a mixer for motors at the given (x forward, y right) positions,
+    ///          each with the sign of its contribution to a clockwise yaw. Roll & pitch
+    ///          are scaled so the furthest motor gets 0.5, like the presets.
+    pub fn from_geometry(positions: &[(f32, f32); N], yaw_signs: &[f32; N]) -> Self {
+        let (mut max_x, mut max_y) = (0.0f32, 0.0f32);
+        for (x, y) in positions.iter() {
+            max_x = max_x.max(x.abs());
+            max_y = max_y.max(y.abs());
+        }
+        let mut table = Matrix::zeros();
+        for (i, ((x, y), yaw)) in positions.iter().zip(yaw_signs.iter()).enumerate() {
+            table.data[i] = [1.0, -0.5 * y / max_y, -0.5 * x / max_x, 0.5 * yaw];
+        }
+        Self::new(table)
+    }
+
+    /// EFFECTS: Marks an output as a servo. Servo outputs are centred on 0.5, clamped to
+    ///          [0,1] and excluded from desaturation.
+    pub fn set_servo(&mut self, output: usize, servo: bool) {
+        self.servos[output] = servo;
+    }
+
+    pub fn table(&self) -> &Matrix<f32, N, 4> {
+        &self.table
+    }
+
+    /// EFFECTS: Returns whether the last mix had to scale down the attitude demands to
+    ///          fit the motor range
+    pub fn is_saturated(&self) -> bool {
+        self.saturated
+    }
+
+    /// EFFECTS: Returns per-output thrust in [0,1]. If the attitude demands span more
+    ///          than the motor range they are scaled down, then throttle is shifted so no
+    ///          motor leaves [0,1] and the attitude differential is preserved.
+    pub fn mix(&mut self, demands: &AxisDemands) -> [f32; N] {
+        let throttle = demands.throttle.clamp(0.0, 1.0);
+        let mut attitude = [0.0; N];
+        for (i, a) in attitude.iter_mut().enumerate() {
+            let [_, roll, pitch, yaw] = self.table.row(i);
+            *a = demands.roll * roll + demands.pitch * pitch + demands.yaw * yaw;
+        }
+
+        let (min, max) = self.motor_range(&attitude);
+        self.saturated = max - min > 1.0;
+        let scale = if self.saturated {
+            1.0 / (max - min)
+        } else {
+            1.0
+        };
+
+        let mut outputs = [0.0; N];
+        for (i, out) in outputs.iter_mut().enumerate() {
+            let a = if self.servos[i] {
+                attitude[i]
+            } else {
+                attitude[i] * scale
+            };
+            *out = throttle * self.table[(i, 0)] + a;
+        }
+
+        // Shift collective thrust to keep every motor within [0,1]
+        let (min, max) = self.motor_range(&outputs);
+        let shift = if max > 1.0 {
+            1.0 - max
+        } else if min < 0.0 {
+            -min
+        } else {
+            0.0
+        };
+        for (out, servo) in outputs.iter_mut().zip(self.servos.iter()) {
+            *out = if *servo {
+                0.5 + 0.5 * *out
+            } else {
+                *out + shift
+            }
+            .clamp(0.0, 1.0);
+        }
+        outputs
+    }
+
+    /// Returns the smallest & largest value over the motor outputs
+    fn motor_range(&self, values: &[f32; N]) -> (f32, f32) {
+        let mut range = (0.0, 0.0);
+        let motors = values
+            .iter()
+            .zip(self.servos.iter())
+            .filter(|(_, servo)| !**servo);
+        for (i, (v, _)) in motors.enumerate() {
+            range = if i == 0 {
+                (*v, *v)
+            } else {
+                (range.0.min(*v), range.1.max(*v))
+            };
+        }
+        range
+    }
+}
+
+impl Mixer<4> {
+    /// EFFECTS: Returns a quad X mixer. Motor order: rear right, front right, rear left,
+    ///          front left.
+    pub fn quad_x() -> Self {
+        Self::new(Matrix::new([
+            [1.0, -0.5, 0.5, -0.5],
+            [1.0, -0.5, -0.5, 0.5],
+            [1.0, 0.5, 0.5, 0.5],
+            [1.0, 0.5, -0.5, -0.5],
+        ]))
+    }
+
+    /// EFFECTS: Returns a quad + mixer. Motor order: rear, right, left, front.
+    pub fn quad_plus() -> Self {
+        Self::new(Matrix::new([
+            [1.0, 0.0, 0.5, -0.5],
+            [1.0, -0.5, 0.0, 0.5],
+            [1.0, 0.5, 0.0, 0.5],
+            [1.0, 0.0, -0.5, -0.5],
+        ]))
+    }
+
+    /// EFFECTS: Returns a tricopter mixer. Output order: rear, right, left motors then the
+    ///          yaw servo.
+    pub fn tri() -> Self {
+        let mut mixer = Self::new(Matrix::new([
+            [1.0, 0.0, 0.666_667, 0.0],
+            [1.0, -0.5, -0.333_333, 0.0],
+            [1.0, 0.5, -0.333_333, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]));
+        mixer.set_servo(3, true);
+        mixer
+    }
+}
+
+impl Mixer<6> {
+    /// EFFECTS: Returns a hex X mixer. Motor order: rear right, front right, rear left,
+    ///          front left, right, left.
+    pub fn hex_x() -> Self {
+        Self::new(Matrix::new([
+            [1.0, -0.25, 0.433_013, 0.5],
+            [1.0, -0.25, -0.433_013, 0.5],
+            [1.0, 0.25, 0.433_013, -0.5],
+            [1.0, 0.25, -0.433_013, -0.5],
+            [1.0, -0.5, 0.0, -0.5],
+            [1.0, 0.5, 0.0, 0.5],
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f32 = 1e-6;
+
+    fn demands(throttle: f32, roll: f32, pitch: f32, yaw: f32) -> AxisDemands {
+        AxisDemands {
+            throttle,
+            roll,
+            pitch,
+            yaw,
+        }
+    }
+
+    fn assert_outputs<const N: usize>(out: &[f32; N], expected: &[f32; N]) {
+        assert!(
+            out.iter()
+                .zip(expected.iter())
+                .all(|(a, b)| (a - b).abs() < EPS),
+            "{:?} != {:?}",
+            out,
+            expected
+        );
+    }
+
+    #[test]
+    fn unsaturated_mix_adds_attitude_to_throttle() {
+        let mut mixer = Mixer::quad_x();
+        let out = mixer.mix(&demands(0.5, 0.2, 0.0, 0.0));
+        assert_outputs(&out, &[0.4, 0.4, 0.6, 0.6]);
+        assert!(!mixer.is_saturated());
+    }
+
+    #[test]
+    fn throttle_is_shifted_to_keep_the_differential() {
+        let mut mixer = Mixer::quad_x();
+        // Roll 1 spans the motor range exactly, so only throttle moves
+        for throttle in [0.0, 1.0] {
+            let out = mixer.mix(&demands(throttle, 1.0, 0.0, 0.0));
+            assert_outputs(&out, &[0.0, 0.0, 1.0, 1.0]);
+            assert!(!mixer.is_saturated());
+        }
+        let out = mixer.mix(&demands(1.0, 0.0, 0.5, 0.0));
+        assert_outputs(&out, &[1.0, 0.5, 1.0, 0.5]);
+        let out = mixer.mix(&demands(0.0, 0.0, 0.5, 0.0));
+        assert_outputs(&out, &[0.5, 0.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn saturated_mix_scales_attitude_and_shifts_throttle() {
+        let mut mixer = Mixer::quad_x();
+        // Roll & yaw together span 2, so the differential is halved
+        for throttle in [0.0, 1.0] {
+            let out = mixer.mix(&demands(throttle, 1.0, 0.0, 1.0));
+            assert!(mixer.is_saturated());
+            assert_outputs(&out, &[0.0, 0.5, 1.0, 0.5]);
+        }
+        mixer.mix(&demands(0.5, 0.0, 0.0, 0.0));
+        assert!(!mixer.is_saturated());
+    }
+
+    #[test]
+    fn tri_drives_the_yaw_servo() {
+        let mut mixer = Mixer::tri();
+        let out = mixer.mix(&demands(0.5, 0.0, 0.0, 0.0));
+        assert_outputs(&out, &[0.5, 0.5, 0.5, 0.5]);
+        let out = mixer.mix(&demands(0.5, 0.0, 0.0, 0.5));
+        assert_outputs(&out, &[0.5, 0.5, 0.5, 0.75]);
+        // The servo saturates on its own without desaturating the motors
+        let out = mixer.mix(&demands(0.5, 0.0, 0.0, -3.0));
+        assert_outputs(&out, &[0.5, 0.5, 0.5, 0.0]);
+        assert!(!mixer.is_saturated());
+    }
+
+    #[test]
+    fn geometry_reproduces_quad_x() {
+        // Rear right & front left spin clockwise, so they yaw counter-clockwise
+        let positions = [(-1.0, 1.0), (1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)];
+        let mixer = Mixer::from_geometry(&positions, &[-1.0, 1.0, 1.0, -1.0]);
+        let preset = Mixer::quad_x();
+        for i in 0..4 {
+            assert_eq!(mixer.table().row(i), preset.table().row(i), "row {}", i);
+        }
+    }
+}
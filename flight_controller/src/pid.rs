@@ -0,0 +1,292 @@
+use core::f32::consts::PI;
+
+/// Gains for a PID controller
+#[derive(Debug, Copy, Clone, Default)]
+pub struct PidGains {
+    /// Proportional gain
+    pub kp: f32,
+    /// Integral gain (per second)
+    pub ki: f32,
+    /// Derivative gain (seconds)
+    pub kd: f32,
+    /// Setpoint feed-forward gain
+    pub kff: f32,
+}
+
+impl PidGains {
+    pub fn new(kp: f32, ki: f32, kd: f32) -> Self {
+        Self {
+            kp,
+            ki,
+            kd,
+            kff: 0.0,
+        }
+    }
+}
+
+/// A PID controller with derivative on measurement, a first-order low-pass filter on
+/// the derivative term, setpoint feed-forward and integrator anti-windup.
+pub struct Pid {
+    gains: PidGains,
+    /// Output bounds (min, max)
+    output_limits: (f32, f32),
+    /// Bound on the magnitude of the integral term
+    integral_limit: f32,
+    /// Derivative low-pass cutoff frequency (Hz). Zero disables filtering.
+    d_cutoff_hz: f32,
+    /// Integral term, stored in output units so gain changes are bumpless
+    integral: f32,
+    /// Filtered rate of change of the measurement
+    d_filtered: f32,
+    prev_measurement: Option<f32>,
+    frozen: bool,
+    output: f32,
+}
+
+impl Pid {
+    /// EFFECTS: Returns a controller with unbounded output and integral
+    pub fn new(gains: PidGains) -> Self {
+        Self {
+            gains,
+            output_limits: (f32::NEG_INFINITY, f32::INFINITY),
+            integral_limit: f32::INFINITY,
+            d_cutoff_hz: 0.0,
+            integral: 0.0,
+            d_filtered: 0.0,
+            prev_measurement: None,
+            frozen: false,
+            output: 0.0,
+        }
+    }
+
+    pub fn gains(&self) -> PidGains {
+        self.gains
+    }
+
+    /// EFFECTS: Sets the gains. The accumulated integral is kept so output does not jump.
+    pub fn set_gains(&mut self, gains: PidGains) {
+        self.gains = gains;
+    }
+
+    /// EFFECTS: Bounds the controller output. Inverted bounds are swapped and a NaN
+    ///          bound leaves that side unbounded.
+    pub fn set_output_limits(&mut self, min: f32, max: f32) {
+        let min = if min.is_nan() { f32::NEG_INFINITY } else { min };
+        let max = if max.is_nan() { f32::INFINITY } else { max };
+        self.output_limits = (min.min(max), max.max(min));
+    }
+
+    /// EFFECTS: Bounds the magnitude of the integral term. The sign of limit is ignored
+    ///          and NaN leaves the integral unbounded.
+    pub fn set_integral_limit(&mut self, limit: f32) {
+        self.integral_limit = if limit.is_nan() {
+            f32::INFINITY
+        } else {
+            limit.abs()
+        };
+        self.integral = self
+            .integral
+            .clamp(-self.integral_limit, self.integral_limit);
+    }
+
+    /// EFFECTS: Sets the derivative low-pass cutoff frequency (Hz). Zero disables it.
+    pub fn set_d_lowpass(&mut self, cutoff_hz: f32) {
+        self.d_cutoff_hz = cutoff_hz;
+    }
+
+    /// EFFECTS: Stops (or resumes) integration, e.g. while the motors are saturated
+    pub fn set_integrator_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    pub fn is_integrator_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// EFFECTS: Clears the integral and derivative state
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.d_filtered = 0.0;
+        self.prev_measurement = None;
+        self.output = 0.0;
+    }
+
+    /// EFFECTS: Clears the derivative state and preloads the integral so the next update
+    ///          with the same setpoint & measurement produces output, e.g. when taking
+    ///          over from another controller
+    pub fn bumpless_reset(&mut self, setpoint: f32, measurement: f32, output: f32) {
+        self.reset();
+        let p = self.gains.kp * (setpoint - measurement);
+        let ff = self.gains.kff * setpoint;
+        self.integral = (output - p - ff).clamp(-self.integral_limit, self.integral_limit);
+        self.prev_measurement = Some(measurement);
+        self.output = output;
+    }
+
+    /// REQUIRES: dt > 0
+    /// EFFECTS: Advances the controller by dt seconds and returns the new output
+    pub fn update(&mut self, setpoint: f32, measurement: f32, dt: f32) -> f32 {
+        let err = setpoint - measurement;
+        let p = self.gains.kp * err;
+        let ff = self.gains.kff * setpoint;
+
+        // Derivative on measurement avoids a kick when the setpoint steps
+        let rate = match self.prev_measurement {
+            Some(prev) => -(measurement - prev) / dt,
+            None => 0.0,
+        };
+        self.prev_measurement = Some(measurement);
+        self.d_filtered = if self.d_cutoff_hz > 0.0 {
+            let rc = 1.0 / (2.0 * PI * self.d_cutoff_hz);
+            self.d_filtered + (dt / (rc + dt)) * (rate - self.d_filtered)
+        } else {
+            rate
+        };
+        let d = self.gains.kd * self.d_filtered;
+
+        if !self.frozen {
+            let integral = (self.integral + self.gains.ki * err * dt)
+                .clamp(-self.integral_limit, self.integral_limit);
+            // Only integrate if it does not push a saturated output further out of range
+            let unsaturated = p + integral + d + ff;
+            let (min, max) = self.output_limits;
+            let winding_up = (unsaturated > max && integral > self.integral)
+                || (unsaturated < min && integral < self.integral);
+            if !winding_up {
+                self.integral = integral;
+            }
+        }
+
+        let (min, max) = self.output_limits;
+        self.output = (p + self.integral + d + ff).clamp(min, max);
+        self.output
+    }
+
+    /// EFFECTS: Returns the last output
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    /// EFFECTS: Returns the integral term
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.01;
+
+    /// Runs the controller against a first order plant (time constant 0.1 s) driven by
+    /// its output, returning the final measurement
+    fn run_plant(pid: &mut Pid, setpoint: f32, steps: usize) -> f32 {
+        let mut measurement = 0.0;
+        for _ in 0..steps {
+            let output = pid.update(setpoint, measurement, DT);
+            measurement += (output - measurement) * (DT / 0.1);
+        }
+        measurement
+    }
+
+    #[test]
+    fn proportional_step_leaves_steady_state_error() {
+        let mut pid = Pid::new(PidGains::new(4.0, 0.0, 0.0));
+        let measurement = run_plant(&mut pid, 1.0, 500);
+        // Steady state of kp / (1 + kp)
+        assert!((measurement - 0.8).abs() < 1e-3, "{}", measurement);
+    }
+
+    #[test]
+    fn integral_step_reaches_setpoint() {
+        let mut pid = Pid::new(PidGains::new(2.0, 5.0, 0.0));
+        let measurement = run_plant(&mut pid, 1.0, 1000);
+        assert!((measurement - 1.0).abs() < 1e-3, "{}", measurement);
+    }
+
+    #[test]
+    fn output_and_integral_are_clamped() {
+        let mut pid = Pid::new(PidGains::new(1.0, 10.0, 0.0));
+        pid.set_output_limits(-1.0, 1.0);
+        pid.set_integral_limit(0.5);
+        for _ in 0..1000 {
+            assert!(pid.update(100.0, 0.0, DT) <= 1.0);
+        }
+        assert!(pid.integral() <= 0.5);
+    }
+
+    #[test]
+    fn saturated_output_does_not_wind_up() {
+        let mut pid = Pid::new(PidGains::new(1.0, 10.0, 0.0));
+        pid.set_output_limits(-1.0, 1.0);
+        for _ in 0..1000 {
+            pid.update(10.0, 0.0, DT);
+        }
+        // P alone saturates, so the integral must not grow while the error persists
+        assert_eq!(pid.integral(), 0.0);
+        // Output recovers as soon as the error reverses
+        assert!(pid.update(0.0, 0.5, DT) < 0.0);
+    }
+
+    #[test]
+    fn frozen_integrator_holds() {
+        let mut pid = Pid::new(PidGains::new(0.0, 1.0, 0.0));
+        pid.update(1.0, 0.0, DT);
+        let held = pid.integral();
+        pid.set_integrator_frozen(true);
+        for _ in 0..100 {
+            pid.update(1.0, 0.0, DT);
+        }
+        assert_eq!(pid.integral(), held);
+    }
+
+    #[test]
+    fn derivative_ignores_setpoint_steps() {
+        let mut pid = Pid::new(PidGains::new(0.0, 0.0, 1.0));
+        pid.update(0.0, 0.0, DT);
+        assert_eq!(pid.update(10.0, 0.0, DT), 0.0);
+        // A rising measurement is opposed
+        assert!((pid.update(10.0, 0.1, DT) + 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn derivative_filter_smooths_steps() {
+        let mut pid = Pid::new(PidGains::new(0.0, 0.0, 1.0));
+        pid.set_d_lowpass(10.0);
+        pid.update(0.0, 0.0, DT);
+        let first = pid.update(0.0, 0.1, DT);
+        // Unfiltered this would be -10; rc = 1/(20 pi) so alpha = 0.386
+        assert!((first + 3.858).abs() < 1e-2, "{}", first);
+        let mut last = first;
+        for _ in 0..100 {
+            last = pid.update(0.0, 0.1, DT);
+        }
+        assert!(last.abs() < 1e-3, "{}", last);
+    }
+
+    #[test]
+    fn bumpless_reset_holds_output() {
+        let mut pid = Pid::new(PidGains {
+            kp: 2.0,
+            ki: 1.0,
+            kd: 0.5,
+            kff: 0.3,
+        });
+        pid.bumpless_reset(1.0, 0.5, 0.7);
+        assert!((pid.update(1.0, 0.5, 0.0001) - 0.7).abs() < 1e-3);
+    }
+
+    #[test]
+    fn invalid_limits_do_not_panic() {
+        let mut pid = Pid::new(PidGains::new(1.0, 1.0, 0.0));
+        pid.set_output_limits(1.0, -1.0);
+        assert_eq!(pid.update(10.0, 0.0, DT), 1.0);
+        pid.set_output_limits(f32::NAN, 0.5);
+        assert_eq!(pid.update(-10.0, 0.0, DT), -10.0 + pid.integral());
+        pid.set_integral_limit(-0.05);
+        assert!(pid.integral().abs() <= 0.05);
+        pid.set_integral_limit(f32::NAN);
+        pid.update(1.0, 0.0, DT);
+    }
+}
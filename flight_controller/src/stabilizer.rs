@@ -0,0 +1,203 @@
+use elinalgebra::F32x3;
+
+use crate::{Pid, PidGains};
+
+/// A rotation axis of the drone
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Axis {
+    Roll = 0,
+    Pitch = 1,
+    Yaw = 2,
+}
+
+/// Pilot demands in angle mode
+#[derive(Debug, Copy, Clone, Default)]
+pub struct AttitudeSetpoint {
+    /// Desired roll angle (deg)
+    pub roll: f32,
+    /// Desired pitch angle (deg)
+    pub pitch: f32,
+    /// Desired yaw rate, clockwise viewed from above (deg/s)
+    pub yaw_rate: f32,
+    /// Collective throttle in [0,1]
+    pub throttle: f32,
+}
+
+/// Per-axis demands for the motor mixer. Roll, pitch & yaw are in [-1,1] with the
+/// Mixer's sign conventions, so positive yaw turns clockwise viewed from above.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct AxisDemands {
+    pub throttle: f32,
+    pub roll: f32,
+    pub pitch: f32,
+    pub yaw: f32,
+}
+
+/// Cascaded angle mode controller. An outer angle loop on roll & pitch produces rate
+/// setpoints for an inner rate loop on every axis. Yaw is controlled by rate alone.
+pub struct Stabilizer {
+    /// Roll & pitch angle controllers, outputting rates (deg/s)
+    angle: [Pid; 2],
+    /// Roll, pitch & yaw rate controllers, outputting demands in [-1,1]
+    rate: [Pid; 3],
+}
+
+impl Stabilizer {
+    /// EFFECTS: Returns a stabilizer with the given roll & pitch angle gains and roll,
+    ///          pitch & yaw rate gains. Rate demands are unbounded until set with
+    ///          set_max_rate.
+    pub fn new(angle_gains: [PidGains; 2], rate_gains: [PidGains; 3]) -> Self {
+        let mut rate = rate_gains.map(Pid::new);
+        for pid in rate.iter_mut() {
+            pid.set_output_limits(-1.0, 1.0);
+        }
+        Self {
+            angle: angle_gains.map(Pid::new),
+            rate,
+        }
+    }
+
+    /// EFFECTS: Returns the angle controller for the axis, or None for yaw
+    pub fn angle_pid_mut(&mut self, axis: Axis) -> Option<&mut Pid> {
+        self.angle.get_mut(axis as usize)
+    }
+
+    /// EFFECTS: Returns the rate controller for the axis
+    pub fn rate_pid_mut(&mut self, axis: Axis) -> &mut Pid {
+        &mut self.rate[axis as usize]
+    }
+
+    /// EFFECTS: Sets the angle loop gains. Yaw has no angle loop and is ignored.
+    pub fn set_angle_gains(&mut self, axis: Axis, gains: PidGains) {
+        if let Some(pid) = self.angle_pid_mut(axis) {
+            pid.set_gains(gains);
+        }
+    }
+
+    /// EFFECTS: Sets the rate loop gains
+    pub fn set_rate_gains(&mut self, axis: Axis, gains: PidGains) {
+        self.rate_pid_mut(axis).set_gains(gains);
+    }
+
+    /// REQUIRES: max_rate >= 0
+    /// EFFECTS: Bounds the rate (deg/s) the angle loop may request on the axis
+    pub fn set_max_rate(&mut self, axis: Axis, max_rate: f32) {
+        if let Some(pid) = self.angle_pid_mut(axis) {
+            pid.set_output_limits(-max_rate, max_rate);
+        }
+    }
+
+    /// EFFECTS: Freezes the integrators while the motor outputs are saturated
+    pub fn set_saturated(&mut self, saturated: bool) {
+        for pid in self.angle.iter_mut().chain(self.rate.iter_mut()) {
+            pid.set_integrator_frozen(saturated);
+        }
+    }
+
+    /// EFFECTS: Clears all controller state, e.g. on arming
+    pub fn reset(&mut self) {
+        for pid in self.angle.iter_mut().chain(self.rate.iter_mut()) {
+            pid.reset();
+        }
+    }
+
+    /// EFFECTS: Runs both loops with the estimated roll, pitch & yaw (deg) and gyro rates
+    ///          (deg/s) sampled dt seconds after the previous update. The gyro is mounted
+    ///          z up, so its yaw rate is counter-clockwise viewed from above.
+    pub fn update(
+        &mut self,
+        setpoint: &AttitudeSetpoint,
+        attitude: &F32x3,
+        gyro: &F32x3,
+        dt: f32,
+    ) -> AxisDemands {
+        let roll_rate = self.angle[0].update(setpoint.roll, attitude.x, dt);
+        let pitch_rate = self.angle[1].update(setpoint.pitch, attitude.y, dt);
+        // Yaw setpoints & demands are clockwise, the gyro counter-clockwise
+        let yaw_rate = -gyro.z;
+        AxisDemands {
+            throttle: setpoint.throttle,
+            roll: self.rate[0].update(roll_rate, gyro.x, dt),
+            pitch: self.rate[1].update(pitch_rate, gyro.y, dt),
+            yaw: self.rate[2].update(setpoint.yaw_rate, yaw_rate, dt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mixer;
+
+    const DT: f32 = 0.002;
+
+    fn stabilizer() -> Stabilizer {
+        let angle = PidGains::new(4.0, 0.0, 0.0);
+        let rate = PidGains::new(0.01, 0.0, 0.0);
+        Stabilizer::new([angle; 2], [rate; 3])
+    }
+
+    fn hover() -> AttitudeSetpoint {
+        AttitudeSetpoint {
+            throttle: 0.5,
+            ..AttitudeSetpoint::default()
+        }
+    }
+
+    #[test]
+    fn clockwise_disturbance_is_corrected_counter_clockwise() {
+        let mut stabilizer = stabilizer();
+        // Spinning clockwise viewed from above reads negative on the z up gyro
+        let gyro = F32x3::new(0.0, 0.0, -30.0);
+        let demands = stabilizer.update(&hover(), &F32x3::filled(0.0), &gyro, DT);
+        assert!(demands.yaw < 0.0, "{:?}", demands);
+
+        // Counter-clockwise props (FR, RL) produce clockwise torque, so they slow down
+        let out = Mixer::quad_x().mix(&demands);
+        assert!(out[1] < out[0] && out[2] < out[3], "{:?}", out);
+    }
+
+    #[test]
+    fn clockwise_setpoint_demands_clockwise_yaw() {
+        let mut stabilizer = stabilizer();
+        let setpoint = AttitudeSetpoint {
+            yaw_rate: 90.0,
+            ..hover()
+        };
+        let still = F32x3::filled(0.0);
+        let demands = stabilizer.update(&setpoint, &still, &still, DT);
+        assert!(demands.yaw > 0.0, "{:?}", demands);
+        // Already turning clockwise at the setpoint
+        let gyro = F32x3::new(0.0, 0.0, -90.0);
+        let demands = stabilizer.update(&setpoint, &still, &gyro, DT);
+        assert!(demands.yaw.abs() < 1e-6, "{:?}", demands);
+    }
+
+    #[test]
+    fn tilt_error_cascades_through_rate_loop() {
+        let mut stabilizer = stabilizer();
+        stabilizer.set_max_rate(Axis::Roll, 50.0);
+        let setpoint = AttitudeSetpoint {
+            roll: 20.0,
+            pitch: -5.0,
+            ..hover()
+        };
+        let gyro = F32x3::filled(0.0);
+        let demands = stabilizer.update(&setpoint, &F32x3::filled(0.0), &gyro, DT);
+        // Roll rate clamped to 50 deg/s, pitch 4 * -5 = -20 deg/s
+        assert!((demands.roll - 0.5).abs() < 1e-6, "{:?}", demands);
+        assert!((demands.pitch + 0.2).abs() < 1e-6, "{:?}", demands);
+        assert_eq!(demands.throttle, 0.5);
+    }
+
+    #[test]
+    fn rate_demands_are_bounded() {
+        let mut stabilizer = stabilizer();
+        let gyro = F32x3::new(-500.0, 500.0, -500.0);
+        let demands = stabilizer.update(&hover(), &F32x3::filled(0.0), &gyro, DT);
+        assert_eq!(
+            (demands.roll, demands.pitch, demands.yaw),
+            (1.0, -1.0, -1.0)
+        );
+    }
+}
@@ -40,6 +40,20 @@ where
         Ok(())
     }
 
+    /// Reads a big-endian 16 bit register
+    pub fn read_word(&mut self, reg: u8) -> Result<u16, I2cWrapperError<X>> {
+        let mut buff: [u8; 2] = [0; 2];
+        self.read_bytes(reg, &mut buff)?;
+        Ok(u16::from_be_bytes(buff))
+    }
+
+    /// Writes a big-endian 16 bit register
+    pub fn write_word(&mut self, reg: u8, val: u16) -> Result<(), I2cWrapperError<X>> {
+        let [high, low] = val.to_be_bytes();
+        self.i2c.write(self.slave_addr, &[reg, high, low])?;
+        Ok(())
+    }
+
     /// Writes a series of bits to the given register
     pub fn write_bits(
         &mut self,
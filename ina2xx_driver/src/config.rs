@@ -0,0 +1,203 @@
+use crate::consts::*;
+
+/// Which measurements the chip makes, and whether continuously or once when triggered
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Mode {
+    PowerDown = 0,
+    ShuntTriggered = 1,
+    BusTriggered = 2,
+    ShuntBusTriggered = 3,
+    AdcOff = 4,
+    ShuntContinuous = 5,
+    BusContinuous = 6,
+    ShuntBusContinuous = 7,
+}
+
+/// INA219 bus voltage full scale range
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum BusRange {
+    V16 = 0,
+    V32 = 1,
+}
+
+/// INA219 shunt voltage full scale range
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ShuntGain {
+    Mv40 = 0,
+    Mv80 = 1,
+    Mv160 = 2,
+    Mv320 = 3,
+}
+
+impl ShuntGain {
+    /// EFFECTS: Returns the largest shunt voltage (V) measurable at this gain
+    pub fn full_scale(&self) -> f32 {
+        match self {
+            ShuntGain::Mv40 => 0.04,
+            ShuntGain::Mv80 => 0.08,
+            ShuntGain::Mv160 => 0.16,
+            ShuntGain::Mv320 => 0.32,
+        }
+    }
+}
+
+/// INA219 ADC resolution, or number of 12 bit samples averaged
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Ina219Adc {
+    Bits9 = 0b0000,
+    Bits10 = 0b0001,
+    Bits11 = 0b0010,
+    Bits12 = 0b0011,
+    Samples2 = 0b1001,
+    Samples4 = 0b1010,
+    Samples8 = 0b1011,
+    Samples16 = 0b1100,
+    Samples32 = 0b1101,
+    Samples64 = 0b1110,
+    Samples128 = 0b1111,
+}
+
+impl Ina219Adc {
+    /// EFFECTS: Returns the time (us) one reading takes
+    pub fn conversion_time_us(&self) -> u32 {
+        match self {
+            Ina219Adc::Bits9 => 84,
+            Ina219Adc::Bits10 => 148,
+            Ina219Adc::Bits11 => 276,
+            Ina219Adc::Bits12 => 532,
+            Ina219Adc::Samples2 => 1060,
+            Ina219Adc::Samples4 => 2130,
+            Ina219Adc::Samples8 => 4260,
+            Ina219Adc::Samples16 => 8510,
+            Ina219Adc::Samples32 => 17020,
+            Ina219Adc::Samples64 => 34050,
+            Ina219Adc::Samples128 => 68100,
+        }
+    }
+}
+
+/// INA226 number of conversions averaged per reading
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Averaging {
+    Samples1 = 0,
+    Samples4 = 1,
+    Samples16 = 2,
+    Samples64 = 3,
+    Samples128 = 4,
+    Samples256 = 5,
+    Samples512 = 6,
+    Samples1024 = 7,
+}
+
+/// INA226 time taken by a single conversion
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ConversionTime {
+    Us140 = 0,
+    Us204 = 1,
+    Us332 = 2,
+    Us588 = 3,
+    Us1100 = 4,
+    Us2116 = 5,
+    Us4156 = 6,
+    Us8244 = 7,
+}
+
+/// INA219 configuration register contents. Defaults match the power-on state.
+#[derive(Debug, Copy, Clone)]
+pub struct Ina219Config {
+    pub bus_range: BusRange,
+    pub gain: ShuntGain,
+    pub bus_adc: Ina219Adc,
+    pub shunt_adc: Ina219Adc,
+    pub mode: Mode,
+}
+
+impl Default for Ina219Config {
+    fn default() -> Self {
+        Self {
+            bus_range: BusRange::V32,
+            gain: ShuntGain::Mv320,
+            bus_adc: Ina219Adc::Bits12,
+            shunt_adc: Ina219Adc::Bits12,
+            mode: Mode::ShuntBusContinuous,
+        }
+    }
+}
+
+impl Ina219Config {
+    /// EFFECTS: Returns the configuration register value
+    pub fn bits(&self) -> u16 {
+        (self.bus_range as u16) << CONFIG::BRNG_BIT
+            | (self.gain as u16) << CONFIG::PG_BITS.start
+            | (self.bus_adc as u16) << CONFIG::BADC_BITS.start
+            | (self.shunt_adc as u16) << CONFIG::SADC_BITS.start
+            | (self.mode as u16) << CONFIG::MODE_BITS.start
+    }
+}
+
+/// INA226 configuration register contents. Defaults match the power-on state.
+#[derive(Debug, Copy, Clone)]
+pub struct Ina226Config {
+    pub averaging: Averaging,
+    pub bus_conversion: ConversionTime,
+    pub shunt_conversion: ConversionTime,
+    pub mode: Mode,
+}
+
+impl Default for Ina226Config {
+    fn default() -> Self {
+        Self {
+            averaging: Averaging::Samples1,
+            bus_conversion: ConversionTime::Us1100,
+            shunt_conversion: ConversionTime::Us1100,
+            mode: Mode::ShuntBusContinuous,
+        }
+    }
+}
+
+impl Ina226Config {
+    /// EFFECTS: Returns the configuration register value
+    pub fn bits(&self) -> u16 {
+        1 << CONFIG::INA226_RESERVED_BIT
+            | (self.averaging as u16) << CONFIG::AVG_BITS.start
+            | (self.bus_conversion as u16) << CONFIG::VBUSCT_BITS.start
+            | (self.shunt_conversion as u16) << CONFIG::VSHCT_BITS.start
+            | (self.mode as u16) << CONFIG::MODE_BITS.start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ina219_default_is_power_on_value() {
+        assert_eq!(Ina219Config::default().bits(), 0x399F);
+    }
+
+    #[test]
+    fn ina226_default_is_power_on_value() {
+        assert_eq!(Ina226Config::default().bits(), 0x4127);
+    }
+
+    #[test]
+    fn fields_are_packed_in_place() {
+        let ina219 = Ina219Config {
+            bus_range: BusRange::V16,
+            gain: ShuntGain::Mv40,
+            bus_adc: Ina219Adc::Bits9,
+            shunt_adc: Ina219Adc::Samples128,
+            mode: Mode::PowerDown,
+        };
+        // SADC 0b1111 << 3
+        assert_eq!(ina219.bits(), 0x0078);
+        let ina226 = Ina226Config {
+            averaging: Averaging::Samples1024,
+            bus_conversion: ConversionTime::Us140,
+            shunt_conversion: ConversionTime::Us8244,
+            mode: Mode::BusTriggered,
+        };
+        // Reserved bit 14, AVG 0b111 << 9, VSHCT 0b111 << 3, MODE 0b010
+        assert_eq!(ina226.bits(), 0x4E3A);
+    }
+}
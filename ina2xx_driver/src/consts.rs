@@ -0,0 +1,115 @@
+#![allow(non_camel_case_types)]
+
+// Sources: https://www.ti.com/lit/ds/symlink/ina219.pdf
+//          https://www.ti.com/lit/ds/symlink/ina226.pdf
+
+pub struct BitBlock {
+    pub start: u8,
+    pub len: u8,
+}
+
+/// Address with A0 & A1 tied to ground
+pub const INA2XX_ADDR: u8 = 0x40;
+
+/// Largest value of the current register
+pub const CURRENT_FULL_SCALE: f32 = 32768.0;
+
+pub struct CONFIG;
+impl CONFIG {
+    pub const ADDR: u8 = 0x00;
+    pub const RESET_BIT: u8 = 15;
+    pub const MODE_BITS: BitBlock = BitBlock { start: 0, len: 3 };
+    /// INA219 bus voltage range
+    pub const BRNG_BIT: u8 = 13;
+    /// INA219 shunt PGA gain
+    pub const PG_BITS: BitBlock = BitBlock { start: 11, len: 2 };
+    /// INA219 bus & shunt ADC resolution / averaging
+    pub const BADC_BITS: BitBlock = BitBlock { start: 7, len: 4 };
+    pub const SADC_BITS: BitBlock = BitBlock { start: 3, len: 4 };
+    /// INA226 averaging & conversion times
+    pub const AVG_BITS: BitBlock = BitBlock { start: 9, len: 3 };
+    pub const VBUSCT_BITS: BitBlock = BitBlock { start: 6, len: 3 };
+    pub const VSHCT_BITS: BitBlock = BitBlock { start: 3, len: 3 };
+    /// INA226 reserved bit, which resets to 1
+    pub const INA226_RESERVED_BIT: u8 = 14;
+}
+
+pub struct SHUNT_VOLTAGE;
+impl SHUNT_VOLTAGE {
+    pub const ADDR: u8 = 0x01;
+    pub const INA219_LSB: f32 = 10e-6;
+    pub const INA226_LSB: f32 = 2.5e-6;
+}
+
+pub struct BUS_VOLTAGE;
+impl BUS_VOLTAGE {
+    pub const ADDR: u8 = 0x02;
+    pub const INA219_LSB: f32 = 4e-3;
+    pub const INA226_LSB: f32 = 1.25e-3;
+    /// INA219 only; the reading sits above its status bits
+    pub const INA219_SHIFT: u8 = 3;
+    pub const INA219_CNVR_BIT: u8 = 1;
+    pub const INA219_OVF_BIT: u8 = 0;
+}
+
+pub struct POWER;
+impl POWER {
+    pub const ADDR: u8 = 0x03;
+    /// Power LSB as a multiple of the current LSB
+    pub const INA219_LSB_RATIO: f32 = 20.0;
+    pub const INA226_LSB_RATIO: f32 = 25.0;
+}
+
+pub struct CURRENT;
+impl CURRENT {
+    pub const ADDR: u8 = 0x04;
+}
+
+pub struct CALIBRATION;
+impl CALIBRATION {
+    pub const ADDR: u8 = 0x05;
+    /// Scaling constants from the calibration equations
+    pub const INA219_SCALE: f32 = 0.04096;
+    pub const INA226_SCALE: f32 = 0.00512;
+    pub const INA219_MAX: u16 = 0xFFFE;
+    pub const INA226_MAX: u16 = 0x7FFF;
+}
+
+/// INA226 only
+pub struct MASK_ENABLE;
+impl MASK_ENABLE {
+    pub const ADDR: u8 = 0x06;
+    pub const SOL_BIT: u8 = 15;
+    pub const SUL_BIT: u8 = 14;
+    pub const BOL_BIT: u8 = 13;
+    pub const BUL_BIT: u8 = 12;
+    pub const POL_BIT: u8 = 11;
+    pub const CNVR_BIT: u8 = 10;
+    pub const AFF_BIT: u8 = 4;
+    pub const CVRF_BIT: u8 = 3;
+    pub const OVF_BIT: u8 = 2;
+    pub const APOL_BIT: u8 = 1;
+    pub const LEN_BIT: u8 = 0;
+}
+
+/// INA226 only
+pub struct ALERT_LIMIT;
+impl ALERT_LIMIT {
+    pub const ADDR: u8 = 0x07;
+}
+
+/// INA226 only
+pub struct MANUFACTURER_ID;
+impl MANUFACTURER_ID {
+    pub const ADDR: u8 = 0xFE;
+    pub const EXP_RESULT: u16 = 0x5449;
+}
+
+/// INA226 only
+pub struct DIE_ID;
+impl DIE_ID {
+    pub const ADDR: u8 = 0xFF;
+    pub const EXP_RESULT: u16 = 0x2260;
+    /// Low bits hold the die revision
+    pub const MASK: u16 = 0xFFF0;
+}
@@ -0,0 +1,389 @@
+#![no_std]
+
+//! This crate provides a platform-agnostic driver for the TI INA219 and INA226 current &
+//! power monitors. Features include calibration from the shunt resistance, reading bus
+//! voltage, shunt voltage, current & power, ADC configuration and, on the INA226, alert
+//! thresholds.
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+pub use config::*;
+pub use consts::*;
+use i2c_tools::{I2cDevice, I2cWrapperError};
+
+mod config;
+/// Constants for INA219 & INA226 addresses & values
+pub mod consts;
+
+/// Supported chips
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Chip {
+    Ina219,
+    Ina226,
+}
+
+impl Chip {
+    /// EFFECTS: Returns the shunt voltage register LSB (V)
+    pub fn shunt_lsb(&self) -> f32 {
+        match self {
+            Chip::Ina219 => SHUNT_VOLTAGE::INA219_LSB,
+            Chip::Ina226 => SHUNT_VOLTAGE::INA226_LSB,
+        }
+    }
+
+    /// EFFECTS: Returns the bus voltage register LSB (V)
+    pub fn bus_lsb(&self) -> f32 {
+        match self {
+            Chip::Ina219 => BUS_VOLTAGE::INA219_LSB,
+            Chip::Ina226 => BUS_VOLTAGE::INA226_LSB,
+        }
+    }
+
+    /// REQUIRES: shunt_ohms > 0, max_current > 0
+    /// EFFECTS: Returns the calibration measuring up to max_current (A) through the given
+    ///          shunt, or None if it does not fit the calibration register
+    pub fn calibration(&self, shunt_ohms: f32, max_current: f32) -> Option<Calibration> {
+        let (scale, max, power_ratio) = match self {
+            Chip::Ina219 => (
+                CALIBRATION::INA219_SCALE,
+                CALIBRATION::INA219_MAX,
+                POWER::INA219_LSB_RATIO,
+            ),
+            Chip::Ina226 => (
+                CALIBRATION::INA226_SCALE,
+                CALIBRATION::INA226_MAX,
+                POWER::INA226_LSB_RATIO,
+            ),
+        };
+        if !(shunt_ohms > 0.0 && max_current > 0.0) {
+            return None;
+        }
+        let register = scale / (max_current / CURRENT_FULL_SCALE * shunt_ohms);
+        if !(1.0..=max as f32).contains(&register) {
+            return None;
+        }
+        // Truncate like the datasheet, without letting f32 rounding just below a whole
+        // count drop it. Bit 0 of the INA219 register is unused.
+        let register = (register + 1e-3) as u16 & max;
+        if register == 0 {
+            return None;
+        }
+        // Truncating the register makes each count worth slightly more
+        let current_lsb = scale / (register as f32 * shunt_ohms);
+        Some(Calibration {
+            register,
+            current_lsb,
+            power_lsb: current_lsb * power_ratio,
+        })
+    }
+}
+
+/// Calibration register value and the resulting register scales
+#[derive(Debug, Copy, Clone)]
+pub struct Calibration {
+    pub register: u16,
+    /// Amps per count of the current register
+    pub current_lsb: f32,
+    /// Watts per count of the power register
+    pub power_lsb: f32,
+}
+
+/// INA226 alert pin conditions. Limits are in volts or watts.
+#[derive(Debug, Copy, Clone)]
+pub enum Alert {
+    ShuntOverVoltage(f32),
+    ShuntUnderVoltage(f32),
+    BusOverVoltage(f32),
+    BusUnderVoltage(f32),
+    PowerOverLimit(f32),
+    ConversionReady,
+}
+
+/// Status flags. Alert is only raised by the INA226.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Flags {
+    /// The alert condition has occurred
+    pub alert: bool,
+    pub conversion_ready: bool,
+    /// Current or power overflowed their registers
+    pub overflow: bool,
+}
+
+/// The INA219/INA226 driver struct
+pub struct Ina2xx<T> {
+    /// I2c device wrapper
+    i2c: I2cDevice<T>,
+    chip: Chip,
+    calibration: Option<Calibration>,
+}
+
+impl<T, E> Ina2xx<T>
+where
+    T: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Creates a new driver for the chip at the default address
+    pub fn new(i2c: T, chip: Chip) -> Self {
+        Self::new_with_addr(i2c, chip, INA2XX_ADDR)
+    }
+
+    /// Creates a new driver for the chip at addr, set by its A0 & A1 pins
+    pub fn new_with_addr(i2c: T, chip: Chip, addr: u8) -> Self {
+        Ina2xx {
+            i2c: I2cDevice::new(i2c, addr),
+            chip,
+            calibration: None,
+        }
+    }
+
+    pub fn chip(&self) -> Chip {
+        self.chip
+    }
+
+    pub fn calibration(&self) -> Option<&Calibration> {
+        self.calibration.as_ref()
+    }
+
+    /// Resets the chip, verifying the INA226 ids
+    pub fn init<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Ina2xxError<E>> {
+        if self.chip == Chip::Ina226 {
+            self.verify_ids()?;
+        }
+        self.reset(delay)
+    }
+
+    /// Resets all registers to their power-on values, clearing the calibration
+    pub fn reset<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Ina2xxError<E>> {
+        self.i2c.write_word(CONFIG::ADDR, 1 << CONFIG::RESET_BIT)?;
+        delay.delay_ms(1);
+        self.calibration = None;
+        Ok(())
+    }
+
+    /// Calibrates for max_current (A) through a shunt of shunt_ohms, enabling current &
+    /// power readings
+    pub fn calibrate(&mut self, shunt_ohms: f32, max_current: f32) -> Result<(), Ina2xxError<E>> {
+        let calibration = self
+            .chip
+            .calibration(shunt_ohms, max_current)
+            .ok_or(Ina2xxError::InvalidCalibration)?;
+        self.i2c
+            .write_word(CALIBRATION::ADDR, calibration.register)?;
+        self.calibration = Some(calibration);
+        Ok(())
+    }
+
+    /// Sets the INA219 range, gain, ADC & mode
+    pub fn set_ina219_config(&mut self, config: &Ina219Config) -> Result<(), Ina2xxError<E>> {
+        self.require(Chip::Ina219)?;
+        self.i2c.write_word(CONFIG::ADDR, config.bits())?;
+        Ok(())
+    }
+
+    /// Sets the INA226 averaging, conversion times & mode
+    pub fn set_ina226_config(&mut self, config: &Ina226Config) -> Result<(), Ina2xxError<E>> {
+        self.require(Chip::Ina226)?;
+        self.i2c.write_word(CONFIG::ADDR, config.bits())?;
+        Ok(())
+    }
+
+    /// Sets the operating mode, keeping the rest of the configuration
+    pub fn set_mode(&mut self, mode: Mode) -> Result<(), Ina2xxError<E>> {
+        const BITS: BitBlock = CONFIG::MODE_BITS;
+        let mask = ((1 << BITS.len) - 1) << BITS.start;
+        let config = self.i2c.read_word(CONFIG::ADDR)?;
+        self.i2c
+            .write_word(CONFIG::ADDR, config & !mask | (mode as u16) << BITS.start)?;
+        Ok(())
+    }
+
+    /// Reads the voltage across the shunt (V)
+    pub fn read_shunt_voltage(&mut self) -> Result<f32, Ina2xxError<E>> {
+        let raw = self.i2c.read_word(SHUNT_VOLTAGE::ADDR)? as i16;
+        Ok(raw as f32 * self.chip.shunt_lsb())
+    }
+
+    /// Reads the bus voltage (V)
+    pub fn read_bus_voltage(&mut self) -> Result<f32, Ina2xxError<E>> {
+        let raw = self.i2c.read_word(BUS_VOLTAGE::ADDR)?;
+        let raw = match self.chip {
+            Chip::Ina219 => raw >> BUS_VOLTAGE::INA219_SHIFT,
+            Chip::Ina226 => raw,
+        };
+        Ok(raw as f32 * self.chip.bus_lsb())
+    }
+
+    /// Reads the current (A). Requires calibration.
+    pub fn read_current(&mut self) -> Result<f32, Ina2xxError<E>> {
+        let current_lsb = self.calibrated()?.current_lsb;
+        let raw = self.i2c.read_word(CURRENT::ADDR)? as i16;
+        Ok(raw as f32 * current_lsb)
+    }
+
+    /// Reads the power (W). Requires calibration.
+    pub fn read_power(&mut self) -> Result<f32, Ina2xxError<E>> {
+        let power_lsb = self.calibrated()?.power_lsb;
+        let raw = self.i2c.read_word(POWER::ADDR)?;
+        Ok(raw as f32 * power_lsb)
+    }
+
+    /// Reads the status flags. On the INA226 this clears a latched alert.
+    pub fn read_flags(&mut self) -> Result<Flags, Ina2xxError<E>> {
+        Ok(match self.chip {
+            Chip::Ina219 => {
+                let raw = self.i2c.read_word(BUS_VOLTAGE::ADDR)?;
+                Flags {
+                    alert: false,
+                    conversion_ready: raw & 1 << BUS_VOLTAGE::INA219_CNVR_BIT != 0,
+                    overflow: raw & 1 << BUS_VOLTAGE::INA219_OVF_BIT != 0,
+                }
+            }
+            Chip::Ina226 => {
+                let raw = self.i2c.read_word(MASK_ENABLE::ADDR)?;
+                Flags {
+                    alert: raw & 1 << MASK_ENABLE::AFF_BIT != 0,
+                    conversion_ready: raw & 1 << MASK_ENABLE::CVRF_BIT != 0,
+                    overflow: raw & 1 << MASK_ENABLE::OVF_BIT != 0,
+                }
+            }
+        })
+    }
+
+    /// Sets the single condition that drives the INA226 alert pin. Latched alerts hold
+    /// until the flags are read.
+    pub fn set_alert(
+        &mut self,
+        alert: Alert,
+        latch: bool,
+        active_high: bool,
+    ) -> Result<(), Ina2xxError<E>> {
+        self.require(Chip::Ina226)?;
+        let (bit, limit) = match alert {
+            Alert::ShuntOverVoltage(v) => (MASK_ENABLE::SOL_BIT, self.shunt_limit(v)),
+            Alert::ShuntUnderVoltage(v) => (MASK_ENABLE::SUL_BIT, self.shunt_limit(v)),
+            Alert::BusOverVoltage(v) => (MASK_ENABLE::BOL_BIT, self.bus_limit(v)),
+            Alert::BusUnderVoltage(v) => (MASK_ENABLE::BUL_BIT, self.bus_limit(v)),
+            Alert::PowerOverLimit(w) => {
+                let power_lsb = self.calibrated()?.power_lsb;
+                (MASK_ENABLE::POL_BIT, (w / power_lsb) as u16)
+            }
+            Alert::ConversionReady => (MASK_ENABLE::CNVR_BIT, 0),
+        };
+        self.i2c.write_word(ALERT_LIMIT::ADDR, limit)?;
+        self.i2c.write_word(
+            MASK_ENABLE::ADDR,
+            1 << bit
+                | (active_high as u16) << MASK_ENABLE::APOL_BIT
+                | (latch as u16) << MASK_ENABLE::LEN_BIT,
+        )?;
+        Ok(())
+    }
+
+    /// Disables the INA226 alert pin
+    pub fn clear_alert(&mut self) -> Result<(), Ina2xxError<E>> {
+        self.require(Chip::Ina226)?;
+        self.i2c.write_word(MASK_ENABLE::ADDR, 0)?;
+        Ok(())
+    }
+
+    fn verify_ids(&mut self) -> Result<(), Ina2xxError<E>> {
+        let manufacturer = self.i2c.read_word(MANUFACTURER_ID::ADDR)?;
+        if manufacturer != MANUFACTURER_ID::EXP_RESULT {
+            return Err(Ina2xxError::InvalidDeviceId(manufacturer));
+        }
+        let die = self.i2c.read_word(DIE_ID::ADDR)?;
+        if die & DIE_ID::MASK != DIE_ID::EXP_RESULT {
+            return Err(Ina2xxError::InvalidDeviceId(die));
+        }
+        Ok(())
+    }
+
+    fn require(&self, chip: Chip) -> Result<(), Ina2xxError<E>> {
+        if self.chip == chip {
+            Ok(())
+        } else {
+            Err(Ina2xxError::Unsupported)
+        }
+    }
+
+    fn calibrated(&self) -> Result<&Calibration, Ina2xxError<E>> {
+        self.calibration.as_ref().ok_or(Ina2xxError::NotCalibrated)
+    }
+
+    fn shunt_limit(&self, volts: f32) -> u16 {
+        (volts / self.chip.shunt_lsb()) as i16 as u16
+    }
+
+    fn bus_limit(&self, volts: f32) -> u16 {
+        (volts / self.chip.bus_lsb()) as u16
+    }
+}
+
+#[derive(Debug)]
+pub enum Ina2xxError<T> {
+    I2c(T),
+    InvalidChipId(u8),
+    /// The INA226 manufacturer or die id did not match
+    InvalidDeviceId(u16),
+    /// The shunt & max current do not fit the calibration register
+    InvalidCalibration,
+    /// Current & power need calibrating first
+    NotCalibrated,
+    /// The chip does not have this feature
+    Unsupported,
+}
+
+impl<E> From<I2cWrapperError<E>> for Ina2xxError<E> {
+    fn from(e: I2cWrapperError<E>) -> Self {
+        match e {
+            I2cWrapperError::I2c(x) => Ina2xxError::I2c(x),
+            I2cWrapperError::InvalidChipId(x) => Ina2xxError::InvalidChipId(x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() <= b.abs() * 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn ina219_calibration() {
+        // 0.1 ohm shunt with a 100 uA current LSB: Cal = 0.04096 / (100e-6 * 0.1)
+        let cal = Chip::Ina219.calibration(0.1, 100e-6 * 32768.0).unwrap();
+        assert_eq!(cal.register, 4096);
+        assert_close(cal.current_lsb, 100e-6);
+        assert_close(cal.power_lsb, 2e-3);
+    }
+
+    #[test]
+    fn ina219_calibration_clears_bit_0() {
+        // Cal = 0.04096 / (3 A / 32768 * 0.1) = 4473.9, truncated to 4473 then masked
+        let cal = Chip::Ina219.calibration(0.1, 3.0).unwrap();
+        assert_eq!(cal.register, 4472);
+        assert_close(cal.current_lsb, 0.04096 / (4472.0 * 0.1));
+    }
+
+    #[test]
+    fn ina226_calibration() {
+        // 2 mohm shunt with a 1 mA current LSB: Cal = 0.00512 / (1e-3 * 0.002)
+        let cal = Chip::Ina226.calibration(0.002, 1e-3 * 32768.0).unwrap();
+        assert_eq!(cal.register, 2560);
+        assert_close(cal.current_lsb, 1e-3);
+        assert_close(cal.power_lsb, 25e-3);
+    }
+
+    #[test]
+    fn calibration_out_of_range_is_rejected() {
+        // Too fine a current LSB overflows the register
+        assert!(Chip::Ina226.calibration(0.002, 0.001).is_none());
+        assert!(Chip::Ina219.calibration(0.1, 0.001).is_none());
+        // Too coarse a current LSB truncates to zero
+        assert!(Chip::Ina219.calibration(100.0, 1e6).is_none());
+        assert!(Chip::Ina226.calibration(0.0, 1.0).is_none());
+        assert!(Chip::Ina226.calibration(0.1, -1.0).is_none());
+    }
+}
@@ -0,0 +1,263 @@
+/// DShot bit rate
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DshotSpeed {
+    Dshot150,
+    Dshot300,
+    Dshot600,
+}
+
+impl DshotSpeed {
+    /// EFFECTS: Returns the bit rate in bits per second
+    pub fn bit_rate(&self) -> u32 {
+        match self {
+            DshotSpeed::Dshot150 => 150_000,
+            DshotSpeed::Dshot300 => 300_000,
+            DshotSpeed::Dshot600 => 600_000,
+        }
+    }
+
+    /// EFFECTS: Returns the length of one bit in nanoseconds
+    pub fn bit_period_ns(&self) -> u32 {
+        1_000_000_000 / self.bit_rate()
+    }
+}
+
+/// Special DShot commands. Commands are only acted on while the motor is stopped.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum DshotCommand {
+    MotorStop = 0,
+    Beep1 = 1,
+    Beep2 = 2,
+    Beep3 = 3,
+    Beep4 = 4,
+    Beep5 = 5,
+    EscInfo = 6,
+    SpinDirection1 = 7,
+    SpinDirection2 = 8,
+    ThreeDModeOff = 9,
+    ThreeDModeOn = 10,
+    SettingsRequest = 11,
+    SaveSettings = 12,
+    ExtendedTelemetryEnable = 13,
+    ExtendedTelemetryDisable = 14,
+    SpinDirectionNormal = 20,
+    SpinDirectionReversed = 21,
+    Led0On = 22,
+    Led1On = 23,
+    Led2On = 24,
+    Led3On = 25,
+    Led0Off = 26,
+    Led1Off = 27,
+    Led2Off = 28,
+    Led3Off = 29,
+    AudioStreamModeToggle = 30,
+    SilentModeToggle = 31,
+}
+
+impl DshotCommand {
+    /// EFFECTS: Returns how many consecutive frames the ESC must receive before it acts
+    ///          on the command. Settings changes are ignored unless repeated.
+    pub fn repeat_count(&self) -> u8 {
+        match self {
+            DshotCommand::SpinDirection1
+            | DshotCommand::SpinDirection2
+            | DshotCommand::ThreeDModeOff
+            | DshotCommand::ThreeDModeOn
+            | DshotCommand::SaveSettings
+            | DshotCommand::ExtendedTelemetryEnable
+            | DshotCommand::ExtendedTelemetryDisable
+            | DshotCommand::SpinDirectionNormal
+            | DshotCommand::SpinDirectionReversed => 10,
+            _ => 1,
+        }
+    }
+}
+
+/// A 16 bit DShot frame: an 11 bit value, the telemetry request bit and a 4 bit CRC,
+/// sent most significant bit first. Values 1-47 are commands, 48-2047 are throttle and
+/// 0 stops the motor.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DshotFrame(u16);
+
+impl DshotFrame {
+    /// Lowest throttle value
+    pub const MIN_THROTTLE: u16 = 48;
+    /// Highest throttle value
+    pub const MAX_THROTTLE: u16 = 2047;
+
+    /// REQUIRES: value <= MAX_THROTTLE
+    /// EFFECTS: Returns a frame carrying the raw value
+    pub fn new(value: u16, telemetry: bool) -> Self {
+        let packet = ((value & 0x07FF) << 1) | telemetry as u16;
+        DshotFrame((packet << 4) | Self::checksum(packet))
+    }
+
+    /// EFFECTS: Returns a throttle frame, clamping throttle to
+    ///          [MIN_THROTTLE, MAX_THROTTLE]
+    pub fn throttle(throttle: u16, telemetry: bool) -> Self {
+        Self::new(
+            throttle.clamp(Self::MIN_THROTTLE, Self::MAX_THROTTLE),
+            telemetry,
+        )
+    }
+
+    /// EFFECTS: Returns a frame for thrust in [0,1]. Zero or less (or NaN) stops the motor,
+    ///          anything above maps linearly onto the throttle range.
+    pub fn from_thrust_pct(pct: f32, telemetry: bool) -> Self {
+        if pct.is_nan() || pct <= 0.0 {
+            return Self::new(0, telemetry);
+        }
+        let span = (Self::MAX_THROTTLE - Self::MIN_THROTTLE) as f32;
+        Self::throttle(
+            Self::MIN_THROTTLE + (span * pct.min(1.0) + 0.5) as u16,
+            telemetry,
+        )
+    }
+
+    /// EFFECTS: Returns a command frame. Commands always request telemetry, as ESCs
+    ///          ignore settings commands without it.
+    pub fn command(command: DshotCommand) -> Self {
+        Self::new(command as u16, true)
+    }
+
+    /// EFFECTS: Returns the frame with its CRC inverted, which tells the ESC to answer
+    ///          with bidirectional eRPM telemetry
+    pub fn to_bidirectional(self) -> Self {
+        DshotFrame(self.0 ^ 0x000F)
+    }
+
+    /// EFFECTS: Returns the frame if the CRC of raw is valid, otherwise None
+    pub fn decode(raw: u16) -> Option<Self> {
+        if Self::checksum(raw >> 4) == raw & 0x000F {
+            Some(DshotFrame(raw))
+        } else {
+            None
+        }
+    }
+
+    /// EFFECTS: Returns the frame if the inverted CRC of a bidirectional raw frame is
+    ///          valid, otherwise None
+    pub fn decode_bidirectional(raw: u16) -> Option<Self> {
+        Self::decode(raw ^ 0x000F).map(Self::to_bidirectional)
+    }
+
+    /// EFFECTS: Returns the 11 bit throttle or command value
+    pub fn value(&self) -> u16 {
+        self.0 >> 5
+    }
+
+    /// EFFECTS: Returns whether the frame requests telemetry
+    pub fn telemetry(&self) -> bool {
+        self.0 & 0x0010 != 0
+    }
+
+    /// EFFECTS: Returns the 4 bit CRC
+    pub fn crc(&self) -> u16 {
+        self.0 & 0x000F
+    }
+
+    /// EFFECTS: Returns the frame as sent on the wire
+    pub fn raw(&self) -> u16 {
+        self.0
+    }
+
+    /// EFFECTS: Returns whether the frame carries a command rather than throttle
+    pub fn is_command(&self) -> bool {
+        self.value() != 0 && self.value() < Self::MIN_THROTTLE
+    }
+
+    /// XOR of the three nibbles of the 12 bit packet
+    fn checksum(packet: u16) -> u16 {
+        (packet ^ (packet >> 4) ^ (packet >> 8)) & 0x000F
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttle_frame_known_vector() {
+        // 1046 without telemetry is 1000001011 0 0110
+        let frame = DshotFrame::new(1046, false);
+        assert_eq!(frame.raw(), 0x82C6);
+        assert_eq!(frame.value(), 1046);
+        assert!(!frame.telemetry());
+        assert_eq!(frame.crc(), 0x6);
+    }
+
+    #[test]
+    fn frame_known_vectors() {
+        assert_eq!(DshotFrame::new(0, false).raw(), 0x0000);
+        assert_eq!(DshotFrame::new(0, true).raw(), 0x0011);
+        assert_eq!(DshotFrame::new(2047, false).raw(), 0xFFEE);
+        assert_eq!(DshotFrame::new(48, false).raw(), 0x0606);
+        assert_eq!(DshotFrame::command(DshotCommand::Beep1).raw(), 0x0033);
+        assert_eq!(
+            DshotFrame::command(DshotCommand::SaveSettings).raw(),
+            0x0198
+        );
+    }
+
+    #[test]
+    fn throttle_is_clamped() {
+        assert_eq!(
+            DshotFrame::throttle(0, false).value(),
+            DshotFrame::MIN_THROTTLE
+        );
+        assert_eq!(
+            DshotFrame::throttle(5000, false).value(),
+            DshotFrame::MAX_THROTTLE
+        );
+    }
+
+    #[test]
+    fn thrust_pct_mapping() {
+        assert_eq!(DshotFrame::from_thrust_pct(0.0, false).value(), 0);
+        assert_eq!(DshotFrame::from_thrust_pct(-1.0, false).value(), 0);
+        assert_eq!(DshotFrame::from_thrust_pct(1.0, false).value(), 2047);
+        assert_eq!(DshotFrame::from_thrust_pct(2.0, false).value(), 2047);
+        assert_eq!(DshotFrame::from_thrust_pct(0.5, false).value(), 1048);
+        assert!(DshotFrame::from_thrust_pct(0.001, true).telemetry());
+    }
+
+    #[test]
+    fn nan_thrust_stops_motor() {
+        assert_eq!(DshotFrame::from_thrust_pct(f32::NAN, false).value(), 0);
+    }
+
+    #[test]
+    fn commands() {
+        let frame = DshotFrame::command(DshotCommand::SpinDirectionReversed);
+        assert!(frame.is_command());
+        assert!(frame.telemetry());
+        assert!(!DshotFrame::new(0, false).is_command());
+        assert!(!DshotFrame::new(48, false).is_command());
+        assert_eq!(DshotCommand::SaveSettings.repeat_count(), 10);
+        assert_eq!(DshotCommand::Beep1.repeat_count(), 1);
+    }
+
+    #[test]
+    fn decode_checks_crc() {
+        assert_eq!(
+            DshotFrame::decode(0x82C6),
+            Some(DshotFrame::new(1046, false))
+        );
+        assert_eq!(DshotFrame::decode(0x82C7), None);
+    }
+
+    #[test]
+    fn bidirectional_inverts_crc() {
+        let frame = DshotFrame::new(1046, false).to_bidirectional();
+        assert_eq!(frame.raw(), 0x82C9);
+        assert_eq!(DshotFrame::decode_bidirectional(0x82C9), Some(frame));
+        assert_eq!(DshotFrame::decode_bidirectional(0x82C6), None);
+    }
+
+    #[test]
+    fn bit_periods() {
+        assert_eq!(DshotSpeed::Dshot150.bit_period_ns(), 6666);
+        assert_eq!(DshotSpeed::Dshot600.bit_period_ns(), 1666);
+    }
+}
@@ -0,0 +1,362 @@
+use pio::{
+    Assembler, InSource, JmpCondition, Label, MovDestination, MovOperation, MovSource,
+    OutDestination, SetDestination, SideSet,
+};
+use rp2040_hal::gpio::{Function, FunctionConfig, Pin, PinId, ValidPinMode};
+use rp2040_hal::pio::{
+    InstalledProgram, PIOBuilder, PIOExt, PinDir, Running, Rx, ShiftDirection, StateMachine,
+    StateMachineIndex, Tx, UninitStateMachine, PIO,
+};
+
+use crate::telemetry::{decode_erpm, decode_gcr, decode_response_samples};
+use crate::{DshotCommand, DshotFrame, DshotSpeed, MotorError, RpmTelemetry, SetupMotor};
+
+type DshotAssembler = Assembler<{ pio::RP2040_MAX_PROGRAM_SIZE }>;
+
+/// PIO cycles per DShot bit. A one is high for 6 cycles, a zero for 3.
+const CYCLES_PER_BIT: u32 = 8;
+/// Words of line samples pushed per bidirectional response
+const RESPONSE_WORDS: usize = 3;
+/// Responses are sent 5/4 as fast as frames and sampled every 2 cycles
+const SAMPLES_PER_RESPONSE_BIT: f32 = CYCLES_PER_BIT as f32 * 0.8 / 2.0;
+/// Average samples missed between the start of a response and the first sample
+const RESPONSE_LEAD_SAMPLES: f32 = 2.0;
+/// Pole count of a typical 5 inch quadcopter motor
+const DEFAULT_POLE_COUNT: u8 = 14;
+
+/// The DShot transmitter installed in a PIO block
+pub struct DshotProgram<P: PIOExt> {
+    program: InstalledProgram<P>,
+    bidirectional: bool,
+}
+
+impl<P: PIOExt> DshotProgram<P> {
+    /// EFFECTS: Returns whether motors using the program read eRPM telemetry
+    pub fn is_bidirectional(&self) -> bool {
+        self.bidirectional
+    }
+}
+
+/// REQUIRES: The PIO block has room for 9 instructions
+/// EFFECTS: Installs the DShot transmitter program, which may be shared by every
+///          DshotMotor on the PIO block. The program repeats the last frame until a new
+///          one is written, so the ESC never sees a gap in the signal.
+pub fn install_dshot_program<P: PIOExt>(pio: &mut PIO<P>) -> Result<DshotProgram<P>, MotorError> {
+    let mut a = DshotAssembler::new_with_side_set(SideSet::new(false, 1, false));
+    let mut wrap_target = a.label();
+    let mut wrap_source = a.label();
+    let mut gap = a.label();
+
+    a.bind(&mut wrap_target);
+    // Take a new frame or, if none is waiting, repeat the last one kept in X
+    a.pull_with_side_set(false, false, 0);
+    a.mov_with_side_set(MovDestination::X, MovOperation::None, MovSource::OSR, 0);
+    emit_frame(&mut a, 0);
+    // Hold the line low for 32 bit periods between frames
+    a.set_with_side_set(SetDestination::Y, 31, 0);
+    a.bind(&mut gap);
+    a.jmp_with_delay_and_side_set(JmpCondition::YDecNonZero, &mut gap, 7, 0);
+    a.bind(&mut wrap_source);
+
+    install(pio, a, wrap_source, wrap_target, false)
+}
+
+/// REQUIRES: The PIO block has room for 23 instructions
+/// EFFECTS: Installs the bidirectional DShot program, which may be shared by every
+///          DshotMotor on the PIO block. The line idles high and each frame written is
+///          sent once, after which the line is released and the eRPM response sampled.
+pub fn install_bidir_dshot_program<P: PIOExt>(
+    pio: &mut PIO<P>,
+) -> Result<DshotProgram<P>, MotorError> {
+    let mut a = DshotAssembler::new_with_side_set(SideSet::new(false, 1, false));
+    let mut wrap_target = a.label();
+    let mut wrap_source = a.label();
+    let mut wait_timeout = a.label();
+    let mut wait_low = a.label();
+    let mut still_high = a.label();
+    let mut no_response = a.label();
+    let mut capture = a.label();
+    let mut sample_loop = a.label();
+
+    a.bind(&mut wrap_target);
+    a.set_with_side_set(SetDestination::PINDIRS, 1, 1);
+    a.pull_with_side_set(false, true, 1);
+    emit_frame(&mut a, 1);
+
+    // Release the line and wait about 64 bit periods for the ESC to pull it low
+    a.set_with_side_set(SetDestination::PINDIRS, 0, 1);
+    a.set_with_side_set(SetDestination::X, 7, 1);
+    a.bind(&mut wait_timeout);
+    a.set_with_side_set(SetDestination::Y, 31, 1);
+    a.bind(&mut wait_low);
+    a.jmp_with_side_set(JmpCondition::PinHigh, &mut still_high, 1);
+    a.jmp_with_side_set(JmpCondition::Always, &mut capture, 1);
+    a.bind(&mut still_high);
+    a.jmp_with_side_set(JmpCondition::YDecNonZero, &mut wait_low, 1);
+    a.jmp_with_side_set(JmpCondition::XDecNonZero, &mut wait_timeout, 1);
+
+    // No response: push empty words so every frame yields the same number of words
+    a.set_with_side_set(SetDestination::X, RESPONSE_WORDS as u8 - 1, 1);
+    a.bind(&mut no_response);
+    // A bit count of 0 shifts in 32 bits
+    a.r#in_with_side_set(InSource::NULL, 0, 1);
+    a.jmp_with_side_set(JmpCondition::XDecNonZero, &mut no_response, 1);
+    a.jmp_with_side_set(JmpCondition::Always, &mut wrap_target, 1);
+
+    // Sample the line every 2 cycles, autopushing each 32 samples
+    a.bind(&mut capture);
+    a.set_with_side_set(SetDestination::X, 31, 1);
+    a.bind(&mut sample_loop);
+    a.r#in_with_delay_and_side_set(InSource::PINS, 1, 1, 1);
+    a.r#in_with_delay_and_side_set(InSource::PINS, 1, 1, 1);
+    a.r#in_with_side_set(InSource::PINS, 1, 1);
+    a.jmp_with_side_set(JmpCondition::XDecNonZero, &mut sample_loop, 1);
+    a.bind(&mut wrap_source);
+
+    install(pio, a, wrap_source, wrap_target, true)
+}
+
+/// Emits the loop shifting a 16 bit frame out of OSR. Bits are pulses away from the idle
+/// level: 6 cycles long for a one and 3 for a zero, out of 8.
+fn emit_frame(a: &mut DshotAssembler, idle: u8) {
+    let pulse = idle ^ 1;
+    let mut bit_loop = a.label();
+    let mut zero = a.label();
+
+    a.bind(&mut bit_loop);
+    a.out_with_side_set(OutDestination::Y, 1, idle);
+    a.jmp_with_delay_and_side_set(JmpCondition::YIsZero, &mut zero, 2, pulse);
+    a.nop_with_delay_and_side_set(2, pulse);
+    a.jmp_with_side_set(
+        JmpCondition::OutputShiftRegisterNotEmpty,
+        &mut bit_loop,
+        idle,
+    );
+    a.bind(&mut zero);
+    a.jmp_with_delay_and_side_set(
+        JmpCondition::OutputShiftRegisterNotEmpty,
+        &mut bit_loop,
+        3,
+        idle,
+    );
+}
+
+fn install<P: PIOExt>(
+    pio: &mut PIO<P>,
+    a: DshotAssembler,
+    wrap_source: Label,
+    wrap_target: Label,
+    bidirectional: bool,
+) -> Result<DshotProgram<P>, MotorError> {
+    let program = a.assemble_with_wrap(wrap_source, wrap_target);
+    let program = pio
+        .install(&program)
+        .map_err(|_| MotorError::NoProgramSpace)?;
+    Ok(DshotProgram {
+        program,
+        bidirectional,
+    })
+}
+
+/// A motor driven by a DShot ESC through an RP2040 PIO state machine
+pub struct DshotMotor<P, SM, I>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+    I: PinId,
+    Function<P>: ValidPinMode<I>,
+{
+    _sm: StateMachine<(P, SM), Running>,
+    tx: Tx<(P, SM)>,
+    rx: Rx<(P, SM)>,
+    _pin: Pin<I, Function<P>>,
+    speed: DshotSpeed,
+    request_telemetry: bool,
+    /// eRPM telemetry, only kept for bidirectional motors
+    rpm: Option<RpmTelemetry>,
+    /// Words of the response being received
+    response: [u32; RESPONSE_WORDS],
+    response_len: usize,
+    initialized: bool,
+    thrust_pct: f32,
+}
+
+impl<P, SM, I> DshotMotor<P, SM, I>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+    I: PinId,
+    Function<P>: ValidPinMode<I>,
+{
+    /// REQUIRES: program was installed in the same PIO block, sys_clk_hz is the system
+    ///           clock frequency and, for bidirectional programs, pin is pulled up
+    /// EFFECTS: Starts the state machine on pin and returns a motor struct wrapper.
+    ///          Bidirectional motors assume 14 pole motors until set_pole_count is called.
+    pub fn new(
+        program: &DshotProgram<P>,
+        sm: UninitStateMachine<(P, SM)>,
+        pin: Pin<I, Function<P>>,
+        speed: DshotSpeed,
+        sys_clk_hz: u32,
+    ) -> Self {
+        let pin_num = I::DYN.num;
+        let (int, frac) = clock_divisor(sys_clk_hz, speed);
+        // Safety: the program is never uninstalled while a motor uses it
+        let installed = unsafe { program.program.share() };
+        let (mut sm, rx, tx) = PIOBuilder::from_program(installed)
+            .side_set_pin_base(pin_num)
+            .set_pins(pin_num, 1)
+            .in_pin_base(pin_num)
+            .jmp_pin(pin_num)
+            .out_shift_direction(ShiftDirection::Left)
+            .pull_threshold(16)
+            .in_shift_direction(ShiftDirection::Left)
+            .autopush(program.bidirectional)
+            .clock_divisor_fixed_point(int, frac)
+            .build(sm);
+        sm.set_pindirs([(pin_num, PinDir::Output)]);
+        DshotMotor {
+            _sm: sm.start(),
+            tx,
+            rx,
+            _pin: pin,
+            speed,
+            request_telemetry: false,
+            rpm: program
+                .bidirectional
+                .then(|| RpmTelemetry::new(DEFAULT_POLE_COUNT)),
+            response: [0; RESPONSE_WORDS],
+            response_len: 0,
+            initialized: false,
+            thrust_pct: 0.0,
+        }
+    }
+
+    /// EFFECTS: Returns the bit rate the motor is driven at
+    pub fn speed(&self) -> DshotSpeed {
+        self.speed
+    }
+
+    /// EFFECTS: Returns whether the motor reads eRPM telemetry
+    pub fn is_bidirectional(&self) -> bool {
+        self.rpm.is_some()
+    }
+
+    /// EFFECTS: Sets whether throttle frames request telemetry from the ESC
+    pub fn set_telemetry_request(&mut self, telemetry: bool) {
+        self.request_telemetry = telemetry;
+    }
+
+    /// REQUIRES: pole_count >= 2
+    /// EFFECTS: Sets the number of magnets in the motor, used to convert eRPM to RPM
+    pub fn set_pole_count(&mut self, pole_count: u8) {
+        if let Some(rpm) = self.rpm.as_mut() {
+            rpm.set_pole_count(pole_count);
+        }
+    }
+
+    /// EFFECTS: Returns the eRPM telemetry, or None if the motor is not bidirectional
+    pub fn rpm_telemetry(&self) -> Option<&RpmTelemetry> {
+        self.rpm.as_ref()
+    }
+
+    /// REQUIRES: The motor is stopped
+    /// EFFECTS: Sends the command as many times as the ESC requires, then resumes sending
+    ///          stop frames. Blocks until the frames are queued.
+    pub fn send_command(&mut self, command: DshotCommand) -> Result<(), MotorError> {
+        if self.thrust_pct > 0.0 {
+            return Err(MotorError::NotStopped);
+        }
+        let frame = DshotFrame::command(command);
+        for _ in 0..command.repeat_count() {
+            self.write_frame(frame);
+        }
+        self.write_frame(DshotFrame::new(0, false));
+        Ok(())
+    }
+
+    /// EFFECTS: Queues a frame, waiting for room in the TX FIFO. Bidirectional motors
+    ///          invert the CRC and read any pending responses first.
+    pub fn write_frame(&mut self, frame: DshotFrame) {
+        let frame = if self.is_bidirectional() {
+            self.poll_telemetry();
+            frame.to_bidirectional()
+        } else {
+            frame
+        };
+        // Left align the frame so it is shifted out most significant bit first
+        while !self.tx.write((frame.raw() as u32) << 16) {}
+    }
+
+    /// EFFECTS: Decodes every response received since the last poll. Bidirectional
+    ///          motors must be polled or written to regularly, as the state machine
+    ///          stalls once its RX FIFO fills.
+    pub fn poll_telemetry(&mut self) {
+        let rpm = match self.rpm.as_mut() {
+            Some(rpm) => rpm,
+            None => return,
+        };
+        while let Some(word) = self.rx.read() {
+            self.response[self.response_len] = word;
+            self.response_len += 1;
+            if self.response_len == RESPONSE_WORDS {
+                self.response_len = 0;
+                rpm.record(
+                    decode_response_samples(
+                        &self.response,
+                        SAMPLES_PER_RESPONSE_BIT,
+                        RESPONSE_LEAD_SAMPLES,
+                    )
+                    .and_then(decode_gcr)
+                    .and_then(decode_erpm),
+                );
+            }
+        }
+    }
+}
+
+impl<P, SM, I> SetupMotor for DshotMotor<P, SM, I>
+where
+    P: PIOExt + FunctionConfig,
+    SM: StateMachineIndex,
+    I: PinId,
+    Function<P>: ValidPinMode<I>,
+{
+    fn set_thrust_pct(&mut self, pct: f32) {
+        // NaN stops the motor rather than passing through clamp
+        let pct = if pct.is_nan() {
+            0.0
+        } else {
+            pct.clamp(0.0, 1.0)
+        };
+        self.write_frame(DshotFrame::from_thrust_pct(pct, self.request_telemetry));
+        self.thrust_pct = pct;
+    }
+
+    fn get_thrust_pct(&mut self) -> f32 {
+        self.thrust_pct
+    }
+
+    fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    fn mark_initialized(&mut self) {
+        self.initialized = true;
+    }
+
+    fn get_rpm(&self) -> Option<f32> {
+        self.rpm.as_ref().and_then(RpmTelemetry::rpm)
+    }
+
+    fn is_telemetry_lost(&self) -> bool {
+        self.rpm.as_ref().is_some_and(RpmTelemetry::is_lost)
+    }
+}
+
+/// Returns the 16.8 fixed point divisor running the state machine at CYCLES_PER_BIT
+/// cycles per DShot bit
+fn clock_divisor(sys_clk_hz: u32, speed: DshotSpeed) -> (u16, u8) {
+    let div = ((sys_clk_hz as u64) << 8) / (speed.bit_rate() * CYCLES_PER_BIT) as u64;
+    ((div >> 8) as u16, div as u8)
+}
@@ -1,27 +1,43 @@
 #![no_std]
 
-extern crate alloc;
+use core::fmt::Debug;
+use core::ops::{Index, IndexMut};
+use core::slice::{Iter, IterMut};
 
-use alloc::boxed::Box;
-
-use cortex_m::delay::Delay;
-use cortex_m::prelude::*;
+use embedded_hal::blocking::delay::DelayMs;
 use embedded_hal::digital::v2::{OutputPin, ToggleableOutputPin};
-use rp2040_hal::gpio::bank0::BankPinId;
-use rp2040_hal::gpio::{Pin, PinId, PinMode, PushPullOutput, ValidPinMode};
-use rp2040_hal::pwm::{
-    Channel, ChannelId, SliceId, SliceMode, ValidPwmOutputPin, ValidSliceMode, A, B,
+use embedded_hal::PwmPin;
+
+pub use dshot::{DshotCommand, DshotFrame, DshotSpeed};
+pub use dshot_pio::{install_bidir_dshot_program, install_dshot_program, DshotMotor, DshotProgram};
+pub use limits::OutputLimits;
+pub use mock::MockPwm;
+pub use motor_config::{MotorConfig, SpinDirection};
+pub use protocol::{EscProtocol, PwmTiming};
+pub use rp2040::Motor;
+pub use telemetry::{
+    decode_erpm, decode_gcr, decode_response_samples, erpm_to_rpm, RpmTelemetry, TelemetryError,
+    DEFAULT_TELEMETRY_LOSS_FRAMES,
 };
 
-/// A motor with a given PWM channel
-pub struct Motor<S, M, C>
+mod dshot;
+mod dshot_pio;
+mod limits;
+mod mock;
+mod motor_config;
+mod protocol;
+mod rp2040;
+mod telemetry;
+
+/// A motor driven by any PWM pin through an analog ESC protocol. Thrust is mapped
+/// linearly onto the protocol's pulse width range.
+pub struct PwmMotor<P>
 where
-    S: SliceId,
-    M: SliceMode + ValidSliceMode<S>,
-    C: ChannelId,
+    P: PwmPin<Duty = u16>,
 {
-    channel: Channel<S, M, C>,
+    pwm: P,
     initialized: bool,
+    protocol: EscProtocol,
     duty_range: (u16, u16),
     thrust_pct: f32,
 }
@@ -38,76 +54,93 @@ pub trait SetupMotor {
     fn mark_initialized(&mut self);
     /// EFFECTS: Returns thrust percentage
     fn get_thrust_pct(&mut self) -> f32;
+    /// EFFECTS: Returns the motor speed (RPM) reported by the ESC, or None if the motor
+    ///          has no telemetry or it was lost
+    fn get_rpm(&self) -> Option<f32> {
+        None
+    }
+    /// EFFECTS: Returns whether the ESC has stopped sending valid telemetry
+    fn is_telemetry_lost(&self) -> bool {
+        false
+    }
 }
 
-impl<S, M> Motor<S, M, A>
+impl<T> SetupMotor for &mut T
 where
-    S: SliceId,
-    M: SliceMode + ValidSliceMode<S>,
+    T: SetupMotor + ?Sized,
 {
-    /// REQUIRES: Output is a valid pin for the given channel
-    /// EFFECTS: Sets the channel output and returns a motor struct wrapper
-    pub fn new_a<P, PM>(
-        mut channel: Channel<S, M, A>,
-        div_int: u8,
-        output: Pin<P, PM>,
-    ) -> Motor<S, M, A>
-    where
-        P: PinId + BankPinId + ValidPwmOutputPin<S, A>,
-        PM: PinMode + ValidPinMode<P>,
-    {
-        channel.output_to(output);
-        let duty_per_ms = channel.get_max_duty() as f32 / div_int as f32;
-        Motor {
-            channel,
-            initialized: false,
-            duty_range: ((duty_per_ms * 0.5) as u16, (duty_per_ms * 2.0) as u16),
-            thrust_pct: 0.0,
-        }
+    fn set_thrust_pct(&mut self, pct: f32) {
+        (**self).set_thrust_pct(pct)
+    }
+
+    fn is_initialized(&self) -> bool {
+        (**self).is_initialized()
+    }
+
+    fn mark_initialized(&mut self) {
+        (**self).mark_initialized()
+    }
+
+    fn get_thrust_pct(&mut self) -> f32 {
+        (**self).get_thrust_pct()
+    }
+
+    fn get_rpm(&self) -> Option<f32> {
+        (**self).get_rpm()
+    }
+
+    fn is_telemetry_lost(&self) -> bool {
+        (**self).is_telemetry_lost()
     }
 }
 
-impl<S, M> Motor<S, M, B>
+impl<P> PwmMotor<P>
 where
-    S: SliceId,
-    M: SliceMode + ValidSliceMode<S>,
+    P: PwmPin<Duty = u16>,
 {
-    /// REQUIRES: Output is a valid pin for the given channel
-    /// EFFECTS: Sets the channel output and returns a motor struct wrapper
-    pub fn new_b<P, PM>(
-        mut channel: Channel<S, M, B>,
-        div_int: u8,
-        output: Pin<P, PM>,
-    ) -> Motor<S, M, B>
-    where
-        P: PinId + BankPinId + ValidPwmOutputPin<S, B>,
-        PM: PinMode + ValidPinMode<P>,
-    {
-        channel.output_to(output);
-        let duty_per_ms = channel.get_max_duty() as f32 / div_int as f32;
-        Motor {
-            channel,
+    /// REQUIRES: pwm wraps at the protocol's frame rate
+    /// EFFECTS: Enables the PWM pin and returns a motor struct wrapper
+    pub fn new(mut pwm: P, protocol: EscProtocol) -> Self {
+        pwm.enable();
+        PwmMotor {
+            duty_range: protocol.duty_range(pwm.get_max_duty()),
+            pwm,
             initialized: false,
-            duty_range: ((duty_per_ms * 0.5) as u16, (duty_per_ms * 2.0) as u16),
+            protocol,
             thrust_pct: 0.0,
         }
     }
+
+    /// EFFECTS: Returns the ESC protocol the motor is driven with
+    pub fn protocol(&self) -> EscProtocol {
+        self.protocol
+    }
+
+    /// EFFECTS: Returns the (min, max) duty range mapped to 0 and full thrust
+    pub fn duty_range(&self) -> (u16, u16) {
+        self.duty_range
+    }
+
+    /// EFFECTS: Returns the underlying PWM pin
+    pub fn pwm(&self) -> &P {
+        &self.pwm
+    }
 }
 
-impl<S, M> SetupMotor for Motor<S, M, A>
+impl<P> SetupMotor for PwmMotor<P>
 where
-    S: SliceId,
-    M: SliceMode + ValidSliceMode<S>,
+    P: PwmPin<Duty = u16>,
 {
     fn set_thrust_pct(&mut self, pct: f32) {
+        let pct = pct.clamp(0.0, 1.0);
         let duty =
             ((self.duty_range.1 - self.duty_range.0) as f32 * pct) as u16 + self.duty_range.0;
-        self.channel.set_duty(duty);
+        self.pwm.set_duty(duty);
         self.thrust_pct = pct;
     }
 
     fn get_thrust_pct(&mut self) -> f32 {
-        return self.thrust_pct;
+        self.thrust_pct
     }
 
     fn is_initialized(&self) -> bool {
@@ -119,100 +152,358 @@ where
     }
 }
 
-impl<S, M> SetupMotor for Motor<S, M, B>
+/// A set of N motors, either all of one type or borrowed as `&mut dyn SetupMotor`.
+/// Thrust set through the manager is shaped by each motor's OutputLimits, then trimmed
+/// and mapped for its ESC by its MotorConfig.
+pub struct MotorManager<M, const N: usize>
 where
-    S: SliceId,
-    M: SliceMode + ValidSliceMode<S>,
+    M: SetupMotor,
 {
-    fn set_thrust_pct(&mut self, pct: f32) {
-        let duty =
-            ((self.duty_range.1 - self.duty_range.0) as f32 * pct) as u16 + self.duty_range.0;
-        self.channel.set_duty(duty);
+    motors: [M; N],
+    configs: [MotorConfig; N],
+    limits: [OutputLimits; N],
+    /// Each motor's output before trim, for slew limiting
+    outputs: [f32; N],
+    /// Time (s) since each motor's output was last set, for slew limiting
+    elapsed: [f32; N],
+    /// Whether each motor's last request was out of range
+    clamped: [bool; N],
+    out_of_range_count: u32,
+}
+
+impl<M, const N: usize> MotorManager<M, N>
+where
+    M: SetupMotor,
+{
+    pub fn new(motors: [M; N]) -> Self {
+        Self {
+            motors,
+            configs: [MotorConfig::default(); N],
+            limits: [OutputLimits::default(); N],
+            outputs: [0.0; N],
+            elapsed: [0.0; N],
+            clamped: [false; N],
+            out_of_range_count: 0,
+        }
     }
 
-    fn get_thrust_pct(&mut self) -> f32 {
-        return self.thrust_pct;
+    pub fn config(&self, idx: usize) -> &MotorConfig {
+        &self.configs[idx]
     }
 
-    fn is_initialized(&self) -> bool {
-        self.initialized
+    /// EFFECTS: Sets a motor's frame position, direction, trim and ESC mode. Takes effect
+    ///          from the next thrust update.
+    pub fn set_config(&mut self, idx: usize, config: MotorConfig) {
+        self.configs[idx] = config;
     }
 
-    fn mark_initialized(&mut self) {
-        self.initialized = true;
+    /// EFFECTS: Returns each motor's (x forward, y right) position on the frame
+    pub fn get_positions(&self) -> [(f32, f32); N] {
+        self.configs.map(|c| c.position)
     }
-}
 
-pub struct MotorManager {
-    motors: [Box<dyn SetupMotor>; 4],
-}
+    /// EFFECTS: Returns the sign of each motor's contribution to a clockwise yaw, for
+    ///          building a mixer
+    pub fn get_yaw_signs(&self) -> [f32; N] {
+        self.configs.map(|c| c.direction.yaw_sign())
+    }
+
+    pub fn limits(&self, idx: usize) -> &OutputLimits {
+        &self.limits[idx]
+    }
+
+    pub fn set_limits(&mut self, idx: usize, limits: OutputLimits) {
+        self.limits[idx] = limits;
+    }
 
-impl MotorManager {
-    pub fn new(motors: [Box<dyn SetupMotor>; 4]) -> Self {
-        Self { motors }
+    pub fn set_all_limits(&mut self, limits: OutputLimits) {
+        self.limits = [limits; N];
     }
 
-    pub fn m0(&mut self) -> &mut Box<dyn SetupMotor> {
-        &mut self.motors[0]
+    /// EFFECTS: Advances the slew limiter by dt seconds. Call once per control loop.
+    pub fn advance(&mut self, dt: f32) {
+        for elapsed in self.elapsed.iter_mut() {
+            *elapsed += dt;
+        }
     }
-    pub fn m1(&mut self) -> &mut Box<dyn SetupMotor> {
-        &mut self.motors[1]
+
+    pub fn get(&mut self, idx: usize) -> Option<&mut M> {
+        self.motors.get_mut(idx)
     }
-    pub fn m2(&mut self) -> &mut Box<dyn SetupMotor> {
-        &mut self.motors[2]
+
+    pub fn iter(&self) -> Iter<'_, M> {
+        self.motors.iter()
     }
-    pub fn m3(&mut self) -> &mut Box<dyn SetupMotor> {
-        &mut self.motors[3]
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, M> {
+        self.motors.iter_mut()
     }
 
-    pub fn setup<I: PinId>(
+    /// REQUIRES: The ESCs are powered, or will be within ESC_ARM_MS
+    /// EFFECTS: Holds zero throttle while the ESCs arm and beep, blinking the LED, then
+    ///          marks every motor initialized. Does not calibrate the ESCs.
+    pub fn setup<L, E, D>(&mut self, indicator_led: &mut L, delay: &mut D) -> Result<(), MotorError>
+    where
+        L: OutputPin<Error = E> + ToggleableOutputPin<Error = E>,
+        E: Debug,
+        D: DelayMs<u16>,
+    {
+        if self.motors.iter().any(|m| m.is_initialized()) {
+            return Err(MotorError::AlreadyInitialized);
+        }
+        self.turn_all_off();
+        blink(indicator_led, delay, ESC_ARM_MS, 100);
+        for motor in self.motors.iter_mut() {
+            motor.mark_initialized();
+        }
+        indicator_led.set_high().unwrap();
+        Ok(())
+    }
+
+    /// REQUIRES: Propellers are removed and the ESCs are powered while full throttle is held
+    /// EFFECTS: Teaches the ESCs the throttle range by holding full throttle for
+    ///          config.high_ms, then zero throttle for config.low_ms, blinking the LED
+    ///          faster during the low phase. Marks every motor initialized.
+    pub fn calibrate<L, E, D>(
         &mut self,
-        indicator_led: &mut Pin<I, PushPullOutput>,
-        delay: &mut Delay,
-    ) -> Result<(), MotorError> {
-        delay.delay_ms(100);
+        indicator_led: &mut L,
+        delay: &mut D,
+        config: &CalibrationConfig,
+    ) where
+        L: OutputPin<Error = E> + ToggleableOutputPin<Error = E>,
+        E: Debug,
+        D: DelayMs<u16>,
+    {
+        // The throttle range spans the ESC's full input, regardless of 3D mode
         for motor in self.motors.iter_mut() {
-            if motor.is_initialized() {
-                return Err(MotorError::AlreadyInitialized);
-            }
             motor.set_thrust_pct(1.0);
         }
-        // Delay 1500 ms
-        for _ in 0..15 {
-            indicator_led.toggle().unwrap();
-            delay.delay_ms(100);
-        }
+        blink(indicator_led, delay, config.high_ms, 100);
         for motor in self.motors.iter_mut() {
             motor.set_thrust_pct(0.0);
         }
-        // Delay 2000 ms
-        for _ in 0..40 {
-            indicator_led.toggle().unwrap();
-            delay.delay_ms(50);
-        }
+        blink(indicator_led, delay, config.low_ms, 50);
         for motor in self.motors.iter_mut() {
             motor.mark_initialized();
         }
+        self.turn_all_off();
         indicator_led.set_high().unwrap();
-        Ok(())
+    }
+
+    /// REQUIRES: Propellers are removed
+    /// EFFECTS: Spins the motor at pct for spin_ms, then stops it. Slew limits are
+    ///          ignored, as no time passes between stopping and spinning up.
+    pub fn test_motor<D: DelayMs<u16>>(
+        &mut self,
+        idx: usize,
+        pct: f32,
+        spin_ms: u16,
+        delay: &mut D,
+    ) {
+        self.turn_all_off();
+        let limits = OutputLimits {
+            max_slew: f32::INFINITY,
+            ..self.limits[idx]
+        };
+        let (output, _) = limits.apply(0.0, pct, 0.0);
+        self.motors[idx].set_thrust_pct(self.configs[idx].to_motor_pct(output));
+        delay.delay_ms(spin_ms);
+        self.turn_all_off();
+    }
+
+    /// REQUIRES: Propellers are removed
+    /// EFFECTS: Spins each motor in turn at pct for spin_ms, pausing for pause_ms between
+    ///          motors, so their order and direction can be checked
+    pub fn test_motors<D: DelayMs<u16>>(
+        &mut self,
+        pct: f32,
+        spin_ms: u16,
+        pause_ms: u16,
+        delay: &mut D,
+    ) {
+        for idx in 0..N {
+            self.test_motor(idx, pct, spin_ms, delay);
+            delay.delay_ms(pause_ms);
+        }
+    }
+
+    /// EFFECTS: Sets a motor's thrust through its output limits and config. Requests
+    ///          outside [0,1] are clamped and counted.
+    pub fn set_thrust_pct(&mut self, idx: usize, pct: f32) {
+        let (output, clamped) = self.limits[idx].apply(self.outputs[idx], pct, self.elapsed[idx]);
+        self.motors[idx].set_thrust_pct(self.configs[idx].to_motor_pct(output));
+        self.outputs[idx] = output;
+        self.elapsed[idx] = 0.0;
+        self.clamped[idx] = clamped;
+        if clamped {
+            self.out_of_range_count = self.out_of_range_count.saturating_add(1);
+        }
     }
 
     pub fn set_all_thrust_pct(&mut self, pct: f32) {
-        for motor in self.motors.iter_mut() {
-            motor.set_thrust_pct(pct);
+        self.set_thrust_pcts(&[pct; N]);
+    }
+
+    pub fn set_thrust_pcts(&mut self, pcts: &[f32; N]) {
+        for (idx, pct) in pcts.iter().enumerate() {
+            self.set_thrust_pct(idx, *pct);
         }
     }
 
+    /// EFFECTS: Stops every motor immediately, ignoring idle and slew limits. 3D mode
+    ///          motors are set to neutral.
     pub fn turn_all_off(&mut self) {
-        self.set_all_thrust_pct(0.0);
+        for (motor, config) in self.motors.iter_mut().zip(self.configs.iter()) {
+            motor.set_thrust_pct(config.stop_pct());
+        }
+        self.outputs = [0.0; N];
+        self.elapsed = [0.0; N];
+    }
+
+    /// EFFECTS: Returns which motors had their last request clamped
+    pub fn get_clamped(&self) -> [bool; N] {
+        self.clamped
+    }
+
+    /// EFFECTS: Returns how many out of range requests have been clamped
+    pub fn get_out_of_range_count(&self) -> u32 {
+        self.out_of_range_count
     }
 
     pub fn get_motor_count(&self) -> usize {
-        self.motors.len()
+        N
+    }
+
+    /// EFFECTS: Returns the speed (RPM) of each motor, None where there is no telemetry
+    pub fn get_rpms(&self) -> [Option<f32>; N] {
+        core::array::from_fn(|i| self.motors[i].get_rpm())
+    }
+
+    /// EFFECTS: Returns which motors have lost telemetry
+    pub fn get_telemetry_lost(&self) -> [bool; N] {
+        core::array::from_fn(|i| self.motors[i].is_telemetry_lost())
+    }
+
+    /// EFFECTS: Returns whether any motor has lost telemetry
+    pub fn any_telemetry_lost(&self) -> bool {
+        self.motors.iter().any(|m| m.is_telemetry_lost())
+    }
+}
+
+impl<M, const N: usize> Index<usize> for MotorManager<M, N>
+where
+    M: SetupMotor,
+{
+    type Output = M;
+    fn index(&self, idx: usize) -> &Self::Output {
+        &self.motors[idx]
+    }
+}
+
+impl<M, const N: usize> IndexMut<usize> for MotorManager<M, N>
+where
+    M: SetupMotor,
+{
+    fn index_mut(&mut self, idx: usize) -> &mut Self::Output {
+        &mut self.motors[idx]
+    }
+}
+
+/// Time (ms) ESCs need at zero throttle to arm after power up
+pub const ESC_ARM_MS: u16 = 2000;
+
+/// How long ESC calibration holds each end of the throttle range
+#[derive(Debug, Copy, Clone)]
+pub struct CalibrationConfig {
+    /// Time (ms) at full throttle, long enough for the ESCs to beep after power up
+    pub high_ms: u16,
+    /// Time (ms) at zero throttle, long enough for the ESCs to confirm
+    pub low_ms: u16,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            high_ms: 1500,
+            low_ms: 2000,
+        }
+    }
+}
+
+/// Toggles the LED every period_ms for duration_ms
+fn blink<L, E, D>(led: &mut L, delay: &mut D, duration_ms: u16, period_ms: u16)
+where
+    L: ToggleableOutputPin<Error = E>,
+    E: Debug,
+    D: DelayMs<u16>,
+{
+    for _ in 0..duration_ms / period_ms {
+        led.toggle().unwrap();
+        delay.delay_ms(period_ms);
     }
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum MotorError {
     AlreadyInitialized,
+    /// The PIO block has no room for the DShot program
+    NoProgramSpace,
+    /// A DShot command was sent while the motor was spinning
+    NotStopped,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the largest thrust it was set to
+    #[derive(Default)]
+    struct RecordingMotor {
+        thrust_pct: f32,
+        peak: f32,
+    }
+
+    impl SetupMotor for RecordingMotor {
+        fn set_thrust_pct(&mut self, pct: f32) {
+            self.thrust_pct = pct;
+            self.peak = self.peak.max(pct);
+        }
+
+        fn is_initialized(&self) -> bool {
+            true
+        }
+
+        fn mark_initialized(&mut self) {}
+
+        fn get_thrust_pct(&mut self) -> f32 {
+            self.thrust_pct
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayMs<u16> for NoDelay {
+        fn delay_ms(&mut self, _ms: u16) {}
+    }
+
+    #[test]
+    fn slew_limits_ramp_with_advance() {
+        let mut manager = MotorManager::new([RecordingMotor::default()]);
+        manager.set_all_limits(OutputLimits::new(0.0, 1.0, 1.0));
+        manager.set_thrust_pct(0, 1.0);
+        assert_eq!(manager[0].thrust_pct, 0.0);
+        manager.advance(0.25);
+        manager.set_thrust_pct(0, 1.0);
+        assert_eq!(manager[0].thrust_pct, 0.25);
+    }
+
+    #[test]
+    fn test_motor_spins_despite_slew_limit() {
+        let mut manager = MotorManager::new([RecordingMotor::default(), RecordingMotor::default()]);
+        manager.set_all_limits(OutputLimits::new(0.1, 1.0, 0.5));
+        manager.test_motor(1, 0.5, 100, &mut NoDelay);
+        assert!((manager[1].peak - 0.55).abs() < 1e-6);
+        assert_eq!(manager[1].thrust_pct, 0.0);
+        assert_eq!(manager[0].peak, 0.0);
+    }
 }
@@ -0,0 +1,107 @@
+/// Per-motor output shaping applied by MotorManager. Requests in [0,1] are mapped onto
+/// [min_idle, max_output], then rate limited.
+#[derive(Debug, Copy, Clone)]
+pub struct OutputLimits {
+    /// Output for a zero request, keeping props spinning in air mode
+    pub min_idle: f32,
+    /// Output for a full request
+    pub max_output: f32,
+    /// Largest change in output per second, at least zero. Infinity disables rate
+    /// limiting.
+    pub max_slew: f32,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        Self {
+            min_idle: 0.0,
+            max_output: 1.0,
+            max_slew: f32::INFINITY,
+        }
+    }
+}
+
+impl OutputLimits {
+    /// REQUIRES: 0 <= min_idle <= max_output <= 1
+    /// EFFECTS: Returns limits with max_slew validated as in set_max_slew
+    pub fn new(min_idle: f32, max_output: f32, max_slew: f32) -> Self {
+        let mut limits = Self {
+            min_idle,
+            max_output,
+            max_slew: f32::INFINITY,
+        };
+        limits.set_max_slew(max_slew);
+        limits
+    }
+
+    /// EFFECTS: Sets the largest change in output per second. Negative rates are treated
+    ///          as zero and NaN disables rate limiting.
+    pub fn set_max_slew(&mut self, max_slew: f32) {
+        self.max_slew = if max_slew.is_nan() {
+            f32::INFINITY
+        } else {
+            max_slew.max(0.0)
+        };
+    }
+
+    /// EFFECTS: Returns the output moving from prev towards request after dt seconds, and
+    ///          whether request was outside [0,1] (or NaN) and had to be clamped
+    pub fn apply(&self, prev: f32, request: f32, dt: f32) -> (f32, bool) {
+        let clamped = request.is_nan() || !(0.0..=1.0).contains(&request);
+        let request = if request.is_nan() {
+            0.0
+        } else {
+            request.clamp(0.0, 1.0)
+        };
+        let target = self.min_idle + request * (self.max_output - self.min_idle);
+        let output = if self.max_slew.is_finite() {
+            // Guards against a negative rate set directly on the field
+            let step = self.max_slew.max(0.0) * dt.max(0.0);
+            target.clamp(prev - step, prev + step)
+        } else {
+            target
+        };
+        (output, clamped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_request_onto_idle_and_ceiling() {
+        let limits = OutputLimits::new(0.1, 0.9, f32::INFINITY);
+        assert_eq!(limits.apply(0.0, 0.0, 0.01), (0.1, false));
+        assert_eq!(limits.apply(0.0, 1.0, 0.01), (0.9, false));
+        assert_eq!(limits.apply(0.0, 2.0, 0.01), (0.9, true));
+        assert_eq!(limits.apply(0.0, f32::NAN, 0.01), (0.1, true));
+    }
+
+    #[test]
+    fn slew_limits_change() {
+        let limits = OutputLimits::new(0.0, 1.0, 2.0);
+        assert_eq!(limits.apply(0.5, 1.0, 0.1).0, 0.7);
+        assert_eq!(limits.apply(0.5, 0.0, 0.1).0, 0.3);
+        assert_eq!(limits.apply(0.5, 1.0, 0.0).0, 0.5);
+    }
+
+    #[test]
+    fn infinite_slew_is_unlimited_even_without_time() {
+        let limits = OutputLimits::default();
+        assert_eq!(limits.apply(0.0, 1.0, 0.0).0, 1.0);
+    }
+
+    #[test]
+    fn invalid_slew_does_not_panic() {
+        let mut limits = OutputLimits::new(0.0, 1.0, -1.0);
+        assert_eq!(limits.max_slew, 0.0);
+        assert_eq!(limits.apply(0.5, 1.0, 0.1).0, 0.5);
+        limits.set_max_slew(f32::NAN);
+        assert_eq!(limits.apply(0.5, 1.0, 0.1).0, 1.0);
+        limits.max_slew = -1.0;
+        assert_eq!(limits.apply(0.5, 1.0, 0.1).0, 0.5);
+        limits.max_slew = 1.0;
+        assert_eq!(limits.apply(0.5, 1.0, -0.1).0, 0.5);
+    }
+}
@@ -0,0 +1,102 @@
+use embedded_hal::PwmPin;
+
+/// An in-memory PWM pin for exercising motor logic off target
+#[derive(Debug, Copy, Clone)]
+pub struct MockPwm {
+    duty: u16,
+    max_duty: u16,
+    enabled: bool,
+}
+
+impl MockPwm {
+    pub fn new(max_duty: u16) -> Self {
+        MockPwm {
+            duty: 0,
+            max_duty,
+            enabled: false,
+        }
+    }
+
+    /// EFFECTS: Returns whether the pin has been enabled
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+impl PwmPin for MockPwm {
+    type Duty = u16;
+
+    fn disable(&mut self) {
+        self.enabled = false;
+    }
+
+    fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    fn get_duty(&self) -> Self::Duty {
+        self.duty
+    }
+
+    fn get_max_duty(&self) -> Self::Duty {
+        self.max_duty
+    }
+
+    fn set_duty(&mut self, duty: Self::Duty) {
+        self.duty = duty;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EscProtocol, MotorManager, PwmMotor, SetupMotor};
+
+    /// Counter top giving 1 duty per us at 50 Hz
+    const MAX_DUTY: u16 = 19_999;
+
+    #[test]
+    fn new_motor_enables_pin() {
+        let motor = PwmMotor::new(MockPwm::new(MAX_DUTY), EscProtocol::StandardPwm);
+        assert!(motor.pwm().is_enabled());
+        assert_eq!(motor.duty_range(), (1000, 2000));
+    }
+
+    #[test]
+    fn thrust_maps_onto_duty_range() {
+        let mut motor = PwmMotor::new(MockPwm::new(MAX_DUTY), EscProtocol::StandardPwm);
+        for (pct, duty) in [(0.0, 1000), (0.25, 1250), (0.5, 1500), (1.0, 2000)] {
+            motor.set_thrust_pct(pct);
+            assert_eq!(motor.pwm().get_duty(), duty);
+            assert_eq!(motor.get_thrust_pct(), pct);
+        }
+    }
+
+    #[test]
+    fn thrust_is_clamped() {
+        let mut motor = PwmMotor::new(MockPwm::new(MAX_DUTY), EscProtocol::StandardPwm);
+        motor.set_thrust_pct(1.5);
+        assert_eq!(motor.pwm().get_duty(), 2000);
+        motor.set_thrust_pct(-0.5);
+        assert_eq!(motor.pwm().get_duty(), 1000);
+    }
+
+    #[test]
+    fn duty_range_follows_protocol() {
+        // 2 kHz frames of 500 us
+        let motor = PwmMotor::new(MockPwm::new(4_999), EscProtocol::OneShot125);
+        assert_eq!(motor.duty_range(), (1250, 2500));
+    }
+
+    #[test]
+    fn manager_drives_mock_motors() {
+        let motors =
+            [0; 2].map(|_| PwmMotor::new(MockPwm::new(MAX_DUTY), EscProtocol::StandardPwm));
+        let mut manager = MotorManager::new(motors);
+        manager.set_thrust_pcts(&[0.5, 0.1]);
+        assert_eq!(manager[0].pwm().get_duty(), 1500);
+        assert_eq!(manager[1].pwm().get_duty(), 1100);
+        manager.turn_all_off();
+        assert!(manager.iter().all(|m| m.pwm().get_duty() == 1000));
+    }
+}
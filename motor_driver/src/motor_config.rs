@@ -0,0 +1,72 @@
+/// Direction a motor spins viewed from above
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SpinDirection {
+    Clockwise,
+    CounterClockwise,
+}
+
+impl SpinDirection {
+    /// EFFECTS: Returns the sign of the motor's contribution to a clockwise yaw. Speeding
+    ///          up a counter clockwise prop yaws the frame clockwise.
+    pub fn yaw_sign(&self) -> f32 {
+        match self {
+            SpinDirection::Clockwise => -1.0,
+            SpinDirection::CounterClockwise => 1.0,
+        }
+    }
+}
+
+/// Where a motor sits on the frame and how its output is driven
+#[derive(Debug, Copy, Clone)]
+pub struct MotorConfig {
+    /// Position (x forward, y right) relative to the centre of mass, in any unit
+    pub position: (f32, f32),
+    pub direction: SpinDirection,
+    /// Output scale correcting for a weak or strong motor
+    pub trim: f32,
+    /// Whether the ESC is in 3D mode, where the midpoint is neutral
+    pub three_d: bool,
+    /// Whether to drive a 3D mode ESC below neutral, spinning the motor backwards
+    pub reversed: bool,
+}
+
+impl Default for MotorConfig {
+    fn default() -> Self {
+        Self {
+            position: (0.0, 0.0),
+            direction: SpinDirection::Clockwise,
+            trim: 1.0,
+            three_d: false,
+            reversed: false,
+        }
+    }
+}
+
+impl MotorConfig {
+    pub fn new(position: (f32, f32), direction: SpinDirection) -> Self {
+        Self {
+            position,
+            direction,
+            ..Self::default()
+        }
+    }
+
+    /// EFFECTS: Returns the thrust sent to the motor for an output in [0,1], after trim
+    ///          and, for 3D mode ESCs, mapping about the neutral midpoint. Analog 3D ESCs
+    ///          only; DShot splits its 3D range differently.
+    pub fn to_motor_pct(&self, output: f32) -> f32 {
+        let output = (output * self.trim).clamp(0.0, 1.0);
+        if !self.three_d {
+            output
+        } else if self.reversed {
+            0.5 - 0.5 * output
+        } else {
+            0.5 + 0.5 * output
+        }
+    }
+
+    /// EFFECTS: Returns the thrust that stops the motor
+    pub fn stop_pct(&self) -> f32 {
+        self.to_motor_pct(0.0)
+    }
+}
@@ -0,0 +1,69 @@
+/// Analog ESC protocols, which encode throttle as the width of a repeating pulse
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EscProtocol {
+    /// 1000-2000 us pulses at 50 Hz
+    StandardPwm,
+    /// 125-250 us pulses at 2 kHz
+    OneShot125,
+    /// 42-84 us pulses at 8 kHz
+    OneShot42,
+    /// 5-25 us pulses at 32 kHz
+    Multishot,
+}
+
+/// Counter settings producing a protocol's frame rate from the system clock. The
+/// counter runs at sys_clk / (div_int + div_frac / 16) and wraps after top.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PwmTiming {
+    pub div_int: u8,
+    pub div_frac: u8,
+    pub top: u16,
+}
+
+impl EscProtocol {
+    /// EFFECTS: Returns the frame rate (Hz)
+    pub fn frequency_hz(&self) -> u32 {
+        match self {
+            EscProtocol::StandardPwm => 50,
+            EscProtocol::OneShot125 => 2_000,
+            EscProtocol::OneShot42 => 8_000,
+            EscProtocol::Multishot => 32_000,
+        }
+    }
+
+    /// EFFECTS: Returns the frame period (us)
+    pub fn period_us(&self) -> f32 {
+        1_000_000.0 / self.frequency_hz() as f32
+    }
+
+    /// EFFECTS: Returns the (min, max) pulse widths (us) for zero and full thrust
+    pub fn pulse_range_us(&self) -> (f32, f32) {
+        match self {
+            EscProtocol::StandardPwm => (1000.0, 2000.0),
+            EscProtocol::OneShot125 => (125.0, 250.0),
+            EscProtocol::OneShot42 => (42.0, 84.0),
+            EscProtocol::Multishot => (5.0, 25.0),
+        }
+    }
+
+    /// REQUIRES: sys_clk_hz / frequency_hz() < 255 * 65536
+    /// EFFECTS: Returns the smallest whole divider, and the top it needs, so that the
+    ///          counter wraps at the frame rate. A small divider keeps pulse resolution.
+    pub fn pwm_timing(&self, sys_clk_hz: u32) -> PwmTiming {
+        let counts = sys_clk_hz / self.frequency_hz();
+        let div = counts.div_ceil(u16::MAX as u32 + 1).max(1);
+        PwmTiming {
+            div_int: div as u8,
+            div_frac: 0,
+            top: (counts / div - 1) as u16,
+        }
+    }
+
+    /// REQUIRES: max_duty is the top of a counter wrapping at the frame rate
+    /// EFFECTS: Returns the (min, max) duty cycle for zero and full thrust
+    pub fn duty_range(&self, max_duty: u16) -> (u16, u16) {
+        let duty_per_us = (max_duty as f32 + 1.0) / self.period_us();
+        let (min, max) = self.pulse_range_us();
+        ((duty_per_us * min) as u16, (duty_per_us * max) as u16)
+    }
+}
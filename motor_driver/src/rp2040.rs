@@ -0,0 +1,72 @@
+use rp2040_hal::gpio::bank0::BankPinId;
+use rp2040_hal::gpio::{Pin, PinId, PinMode, ValidPinMode};
+use rp2040_hal::pwm::{
+    Channel, Slice, SliceId, SliceMode, ValidPwmOutputPin, ValidSliceMode, A, B,
+};
+
+use crate::{EscProtocol, PwmMotor};
+
+/// A motor with a given RP2040 PWM channel
+pub type Motor<S, M, C> = PwmMotor<Channel<S, M, C>>;
+
+impl EscProtocol {
+    /// REQUIRES: sys_clk_hz is the system clock frequency
+    /// EFFECTS: Sets the slice's divider and top for the protocol's frame rate and
+    ///          enables it. Both channels of a slice share the protocol.
+    pub fn configure_slice<S, M>(&self, slice: &mut Slice<S, M>, sys_clk_hz: u32)
+    where
+        S: SliceId,
+        M: SliceMode + ValidSliceMode<S>,
+    {
+        let timing = self.pwm_timing(sys_clk_hz);
+        slice.clr_ph_correct();
+        slice.set_div_int(timing.div_int);
+        slice.set_div_frac(timing.div_frac);
+        slice.set_top(timing.top);
+        slice.enable();
+    }
+}
+
+impl<S, M> PwmMotor<Channel<S, M, A>>
+where
+    S: SliceId,
+    M: SliceMode + ValidSliceMode<S>,
+{
+    /// REQUIRES: Output is a valid pin for the given channel and the slice was configured
+    ///           with protocol
+    /// EFFECTS: Sets the channel output and returns a motor struct wrapper
+    pub fn new_a<P, PM>(
+        mut channel: Channel<S, M, A>,
+        protocol: EscProtocol,
+        output: Pin<P, PM>,
+    ) -> Motor<S, M, A>
+    where
+        P: PinId + BankPinId + ValidPwmOutputPin<S, A>,
+        PM: PinMode + ValidPinMode<P>,
+    {
+        channel.output_to(output);
+        PwmMotor::new(channel, protocol)
+    }
+}
+
+impl<S, M> PwmMotor<Channel<S, M, B>>
+where
+    S: SliceId,
+    M: SliceMode + ValidSliceMode<S>,
+{
+    /// REQUIRES: Output is a valid pin for the given channel and the slice was configured
+    ///           with protocol
+    /// EFFECTS: Sets the channel output and returns a motor struct wrapper
+    pub fn new_b<P, PM>(
+        mut channel: Channel<S, M, B>,
+        protocol: EscProtocol,
+        output: Pin<P, PM>,
+    ) -> Motor<S, M, B>
+    where
+        P: PinId + BankPinId + ValidPwmOutputPin<S, B>,
+        PM: PinMode + ValidPinMode<P>,
+    {
+        channel.output_to(output);
+        PwmMotor::new(channel, protocol)
+    }
+}
@@ -0,0 +1,335 @@
+/// Bits in a bidirectional DShot response: a start bit followed by 20 GCR bits
+const RESPONSE_BITS: u32 = 21;
+
+/// Marks 5 bit codes which are not valid GCR
+const INVALID: u8 = 0xFF;
+
+/// Maps each 5 bit GCR code to the nibble it encodes
+const GCR_DECODE: [u8; 32] = [
+    INVALID, INVALID, INVALID, INVALID, INVALID, INVALID, INVALID, INVALID, //
+    INVALID, 0x9, 0xA, 0xB, INVALID, 0xD, 0xE, 0xF, //
+    INVALID, INVALID, 0x2, 0x3, INVALID, 0x5, 0x6, 0x7, //
+    INVALID, 0x0, 0x8, 0x1, INVALID, 0x4, 0xC, INVALID, //
+];
+
+/// Consecutive missing or corrupt responses after which telemetry counts as lost
+pub const DEFAULT_TELEMETRY_LOSS_FRAMES: u8 = 10;
+
+/// Reasons a bidirectional DShot response could not be read
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TelemetryError {
+    /// The ESC did not answer the frame
+    NoResponse,
+    /// The line did not carry a whole 21 bit response
+    Framing,
+    /// A 5 bit group was not a valid GCR code
+    InvalidGcr,
+    /// The decoded value failed its checksum
+    Checksum,
+    /// The response carried a zero period
+    InvalidPeriod,
+}
+
+/// REQUIRES: words hold line samples taken at a fixed rate, most significant bit first,
+///           starting lead_samples after the falling edge of the start bit
+/// EFFECTS: Recovers the 20 GCR bits of a response from the oversampled line. Every
+///          level change is a one and the bits between changes are zeros.
+pub fn decode_response_samples(
+    words: &[u32],
+    samples_per_bit: f32,
+    lead_samples: f32,
+) -> Result<u32, TelemetryError> {
+    if words.iter().all(|w| *w == 0) {
+        return Err(TelemetryError::NoResponse);
+    }
+    let total = words.len() * 32;
+    let sample = |i: usize| (words[i / 32] >> (31 - i % 32)) & 1;
+
+    let mut value: u32 = 0;
+    let mut bits: u32 = 0;
+    let mut run_start = 0;
+    let mut level = sample(0);
+    for i in 1..=total {
+        if i < total && sample(i) == level {
+            continue;
+        }
+        let run_bits = if i == total {
+            // The line idles after the last change, so the final run fills the frame
+            RESPONSE_BITS.saturating_sub(bits)
+        } else {
+            let mut run = (i - run_start) as f32;
+            if run_start == 0 {
+                run += lead_samples;
+            }
+            ((run / samples_per_bit + 0.5) as u32).max(1)
+        };
+        if run_bits == 0 {
+            break;
+        }
+        bits += run_bits;
+        if bits > RESPONSE_BITS {
+            return Err(TelemetryError::Framing);
+        }
+        value = (value << run_bits) | (1 << (run_bits - 1));
+        if bits == RESPONSE_BITS {
+            break;
+        }
+        run_start = i;
+        level ^= 1;
+    }
+
+    if bits != RESPONSE_BITS {
+        return Err(TelemetryError::Framing);
+    }
+    Ok(value & 0x000F_FFFF)
+}
+
+/// EFFECTS: Decodes 20 GCR bits into the 12 bit eRPM value, checking the checksum
+pub fn decode_gcr(gcr: u32) -> Result<u16, TelemetryError> {
+    let mut decoded: u16 = 0;
+    for i in 0..4 {
+        let nibble = GCR_DECODE[((gcr >> (5 * i)) & 0x1F) as usize];
+        if nibble == INVALID {
+            return Err(TelemetryError::InvalidGcr);
+        }
+        decoded |= (nibble as u16) << (4 * i);
+    }
+    // The nibbles of a valid value XOR to 0xF
+    let csum = decoded ^ (decoded >> 8);
+    let csum = csum ^ (csum >> 4);
+    if csum & 0x000F != 0x000F {
+        return Err(TelemetryError::Checksum);
+    }
+    Ok(decoded >> 4)
+}
+
+/// EFFECTS: Converts a 12 bit eRPM value, a 3 bit exponent and 9 bit mantissa giving the
+///          electrical period in microseconds, into electrical RPM
+pub fn decode_erpm(value: u16) -> Result<u32, TelemetryError> {
+    // The longest period is sent while the motor is stopped
+    if value == 0x0FFF {
+        return Ok(0);
+    }
+    let period_us = ((value & 0x01FF) as u32) << (value >> 9);
+    if period_us == 0 {
+        return Err(TelemetryError::InvalidPeriod);
+    }
+    Ok(60_000_000 / period_us)
+}
+
+/// REQUIRES: pole_count >= 2
+/// EFFECTS: Converts electrical RPM into mechanical RPM for a motor with pole_count
+///          magnets
+pub fn erpm_to_rpm(erpm: u32, pole_count: u8) -> f32 {
+    erpm as f32 / (pole_count / 2) as f32
+}
+
+/// Tracks the eRPM reported by an ESC and whether its telemetry has dropped out
+#[derive(Debug, Copy, Clone)]
+pub struct RpmTelemetry {
+    pole_count: u8,
+    loss_frames: u8,
+    erpm: Option<u32>,
+    missed: u8,
+    last_error: Option<TelemetryError>,
+}
+
+impl RpmTelemetry {
+    /// REQUIRES: pole_count >= 2
+    /// EFFECTS: Returns a tracker with no readings yet
+    pub fn new(pole_count: u8) -> Self {
+        RpmTelemetry {
+            pole_count,
+            loss_frames: DEFAULT_TELEMETRY_LOSS_FRAMES,
+            erpm: None,
+            missed: 0,
+            last_error: None,
+        }
+    }
+
+    /// REQUIRES: pole_count >= 2
+    /// EFFECTS: Sets the number of magnets in the motor
+    pub fn set_pole_count(&mut self, pole_count: u8) {
+        self.pole_count = pole_count;
+    }
+
+    /// EFFECTS: Sets how many consecutive bad responses count as telemetry loss
+    pub fn set_loss_frames(&mut self, frames: u8) {
+        self.loss_frames = frames;
+    }
+
+    /// EFFECTS: Records the outcome of one response
+    pub fn record(&mut self, erpm: Result<u32, TelemetryError>) {
+        match erpm {
+            Ok(erpm) => {
+                self.erpm = Some(erpm);
+                self.missed = 0;
+                self.last_error = None;
+            }
+            Err(err) => {
+                self.missed = self.missed.saturating_add(1);
+                self.last_error = Some(err);
+            }
+        }
+    }
+
+    /// EFFECTS: Returns the last electrical RPM, or None if telemetry is lost
+    pub fn erpm(&self) -> Option<u32> {
+        if self.is_lost() {
+            None
+        } else {
+            self.erpm
+        }
+    }
+
+    /// EFFECTS: Returns the last mechanical RPM, or None if telemetry is lost
+    pub fn rpm(&self) -> Option<f32> {
+        self.erpm().map(|erpm| erpm_to_rpm(erpm, self.pole_count))
+    }
+
+    /// EFFECTS: Returns whether too many consecutive responses were missing or corrupt
+    pub fn is_lost(&self) -> bool {
+        self.missed >= self.loss_frames
+    }
+
+    /// EFFECTS: Returns the number of consecutive bad responses
+    pub fn missed(&self) -> u8 {
+        self.missed
+    }
+
+    /// EFFECTS: Returns why the last response was rejected, if it was
+    pub fn last_error(&self) -> Option<TelemetryError> {
+        self.last_error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// GCR code of each nibble
+    const GCR_ENCODE: [u32; 16] = [
+        0x19, 0x1B, 0x12, 0x13, 0x1D, 0x15, 0x16, 0x17, 0x1A, 0x09, 0x0A, 0x0B, 0x1E, 0x0D, 0x0E,
+        0x0F,
+    ];
+
+    /// Returns the GCR bits an ESC sends for a 12 bit eRPM value
+    fn encode_gcr(value: u16) -> u32 {
+        let csum = !(value ^ (value >> 4) ^ (value >> 8)) & 0x000F;
+        let data = (value << 4) | csum;
+        (0..4).fold(0, |gcr, i| {
+            gcr | GCR_ENCODE[((data >> (4 * i)) & 0x000F) as usize] << (5 * i)
+        })
+    }
+
+    /// Returns the line sampled samples_per_bit times per bit, starting at the falling edge
+    /// of the start bit. Each one bit toggles the line, which idles high.
+    fn line_samples(gcr: u32, samples_per_bit: usize) -> [u32; 3] {
+        let frame = (1 << 20) | gcr;
+        let mut words = [0u32; 3];
+        let mut level = 1;
+        for bit in 0..RESPONSE_BITS {
+            level ^= (frame >> (RESPONSE_BITS - 1 - bit)) & 1;
+            for s in 0..samples_per_bit {
+                let i = bit as usize * samples_per_bit + s;
+                words[i / 32] |= level << (31 - i % 32);
+            }
+        }
+        // Hold the last level to the end of the buffer
+        for i in RESPONSE_BITS as usize * samples_per_bit..96 {
+            words[i / 32] |= level << (31 - i % 32);
+        }
+        words
+    }
+
+    #[test]
+    fn gcr_known_vector() {
+        // 0x1F4 with checksum 0x5 is nibbles 1, F, 4, 5
+        assert_eq!(encode_gcr(0x1F4), 0xDBFB5);
+        assert_eq!(decode_gcr(0xDBFB5), Ok(0x1F4));
+    }
+
+    #[test]
+    fn gcr_round_trips() {
+        for value in [0x000, 0x001, 0x1F4, 0x5A5, 0xABC, 0xFFF] {
+            assert_eq!(decode_gcr(encode_gcr(value)), Ok(value));
+        }
+    }
+
+    #[test]
+    fn gcr_rejects_bad_codes_and_checksums() {
+        // 0x00 is not a GCR code
+        assert_eq!(decode_gcr(0xDBFB5 & !0x1F), Err(TelemetryError::InvalidGcr));
+        // Swap the checksum nibble 5 (0x15) for 6 (0x16)
+        assert_eq!(
+            decode_gcr((0xDBFB5 & !0x1F) | 0x16),
+            Err(TelemetryError::Checksum)
+        );
+    }
+
+    #[test]
+    fn erpm_known_vectors() {
+        assert_eq!(decode_erpm(0x0FFF), Ok(0));
+        // Exponent 0, 500 us period
+        assert_eq!(decode_erpm(0x01F4), Ok(120_000));
+        // Exponent 2, 250 << 2 = 1000 us period
+        assert_eq!(decode_erpm((2 << 9) | 250), Ok(60_000));
+        assert_eq!(decode_erpm(3 << 9), Err(TelemetryError::InvalidPeriod));
+    }
+
+    #[test]
+    fn erpm_to_mechanical_rpm() {
+        assert_eq!(erpm_to_rpm(140_000, 14), 20_000.0);
+        assert_eq!(erpm_to_rpm(0, 14), 0.0);
+    }
+
+    #[test]
+    fn decodes_oversampled_line() {
+        for value in [0x1F4, 0xABC, 0xFFF] {
+            let gcr = encode_gcr(value);
+            let words = line_samples(gcr, 3);
+            assert_eq!(decode_response_samples(&words, 3.0, 0.0), Ok(gcr));
+        }
+    }
+
+    #[test]
+    fn decodes_line_with_lead() {
+        let gcr = encode_gcr(0x1F4);
+        let words = line_samples(gcr, 4);
+        // Drop the first sample, as if sampling began one sample after the edge
+        let shifted = [
+            words[0] << 1 | words[1] >> 31,
+            words[1] << 1 | words[2] >> 31,
+            words[2] << 1 | 1,
+        ];
+        assert_eq!(decode_response_samples(&shifted, 4.0, 1.0), Ok(gcr));
+    }
+
+    #[test]
+    fn silent_line_is_no_response() {
+        assert_eq!(
+            decode_response_samples(&[0, 0, 0], 3.0, 0.0),
+            Err(TelemetryError::NoResponse)
+        );
+    }
+
+    #[test]
+    fn telemetry_loss_and_recovery() {
+        let mut rpm = RpmTelemetry::new(14);
+        assert_eq!(rpm.rpm(), None);
+        rpm.record(Ok(140_000));
+        assert_eq!(rpm.rpm(), Some(20_000.0));
+        for _ in 0..DEFAULT_TELEMETRY_LOSS_FRAMES - 1 {
+            rpm.record(Err(TelemetryError::Checksum));
+        }
+        assert!(!rpm.is_lost());
+        assert_eq!(rpm.erpm(), Some(140_000));
+        rpm.record(Err(TelemetryError::NoResponse));
+        assert!(rpm.is_lost());
+        assert_eq!(rpm.rpm(), None);
+        assert_eq!(rpm.last_error(), Some(TelemetryError::NoResponse));
+        rpm.record(Ok(70_000));
+        assert!(!rpm.is_lost());
+        assert_eq!(rpm.rpm(), Some(10_000.0));
+    }
+}
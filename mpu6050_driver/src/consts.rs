@@ -17,6 +17,28 @@ pub const MPU_ADDR: u8 = 0x68;
 pub const TEMP_OFFSET: f32 = 36.53;
 pub const TEMP_SENSITIVITY: f32 = 340.0;
 
+/// Factory self-test codes. Gyro codes are 5 bits; accel codes are 5 bits split between
+/// the high bits of SELF_TEST_X/Y/Z and SELF_TEST_A.
+pub struct SELF_TEST;
+
+impl SELF_TEST {
+    /// SELF_TEST_X, Y, Z & A
+    pub const ADDR: u8 = 0x0d;
+    pub const BYTES: ByteBlock = ByteBlock { start: 0, len: 4 };
+    pub const XG_TEST_BITS: BitBlock = BitBlock { start: 4, len: 5 };
+    pub const XA_TEST_HIGH_BITS: BitBlock = BitBlock { start: 7, len: 3 };
+    /// Shift of each axis' low accel bits in SELF_TEST_A, from X to Z
+    pub const A_TEST_LOW_SHIFTS: [u8; 3] = [4, 2, 0];
+    /// Largest deviation (%) of the self-test response from factory trim
+    pub const TOLERANCE: f32 = 14.0;
+}
+
+pub struct SMPLRT_DIV;
+
+impl SMPLRT_DIV {
+    pub const ADDR: u8 = 0x19;
+}
+
 pub struct GYRO_CONFIG;
 
 impl GYRO_CONFIG {
@@ -59,6 +81,104 @@ impl CONFIG {
     pub const DLPF_CFG_BITS: BitBlock = BitBlock { start: 0, len: 3 };
 }
 
+pub struct MOT_THR;
+
+impl MOT_THR {
+    pub const ADDR: u8 = 0x1f;
+    /// Gs per count
+    pub const LSB: f32 = 0.002;
+}
+
+pub struct MOT_DUR;
+
+impl MOT_DUR {
+    pub const ADDR: u8 = 0x20;
+    /// Milliseconds per count
+    pub const LSB_MS: u16 = 1;
+}
+
+pub struct ZRMOT_THR;
+
+impl ZRMOT_THR {
+    pub const ADDR: u8 = 0x21;
+    /// Gs per count
+    pub const LSB: f32 = 0.002;
+}
+
+pub struct ZRMOT_DUR;
+
+impl ZRMOT_DUR {
+    pub const ADDR: u8 = 0x22;
+    /// Milliseconds per count
+    pub const LSB_MS: u16 = 64;
+}
+
+pub struct FIFO_EN;
+
+impl FIFO_EN {
+    pub const ADDR: u8 = 0x23;
+    pub const TEMP_FIFO_EN_BIT: u8 = 7;
+    pub const XG_FIFO_EN_BIT: u8 = 6;
+    pub const YG_FIFO_EN_BIT: u8 = 5;
+    pub const ZG_FIFO_EN_BIT: u8 = 4;
+    pub const ACCEL_FIFO_EN_BIT: u8 = 3;
+}
+
+pub struct INT_PIN_CFG;
+
+impl INT_PIN_CFG {
+    pub const ADDR: u8 = 0x37;
+    pub const INT_LEVEL_BIT: u8 = 7;
+    pub const INT_OPEN_BIT: u8 = 6;
+    pub const LATCH_INT_EN_BIT: u8 = 5;
+    pub const INT_RD_CLEAR_BIT: u8 = 4;
+}
+
+pub struct INT_ENABLE;
+
+impl INT_ENABLE {
+    pub const ADDR: u8 = 0x38;
+    pub const MOT_EN_BIT: u8 = 6;
+    pub const ZMOT_EN_BIT: u8 = 5;
+    pub const FIFO_OFLOW_EN_BIT: u8 = 4;
+    pub const DATA_RDY_EN_BIT: u8 = 0;
+}
+
+pub struct INT_STATUS;
+
+impl INT_STATUS {
+    pub const ADDR: u8 = 0x3a;
+    pub const MOT_INT_BIT: u8 = 6;
+    pub const ZMOT_INT_BIT: u8 = 5;
+    pub const FIFO_OFLOW_INT_BIT: u8 = 4;
+    pub const DATA_RDY_INT_BIT: u8 = 0;
+}
+
+pub struct USER_CTRL;
+
+impl USER_CTRL {
+    pub const ADDR: u8 = 0x6a;
+    pub const FIFO_EN_BIT: u8 = 6;
+    pub const I2C_MST_EN_BIT: u8 = 5;
+    pub const FIFO_RESET_BIT: u8 = 2;
+    pub const SIG_COND_RESET_BIT: u8 = 0;
+}
+
+pub struct FIFO_COUNT;
+
+impl FIFO_COUNT {
+    pub const ADDR: u8 = 0x72;
+    pub const BYTES: ByteBlock = ByteBlock { start: 0, len: 2 };
+}
+
+pub struct FIFO_R_W;
+
+impl FIFO_R_W {
+    pub const ADDR: u8 = 0x74;
+    /// Bytes the FIFO holds
+    pub const SIZE: u16 = 1024;
+}
+
 pub struct PWR_MGMT_2;
 
 impl PWR_MGMT_2 {
@@ -87,6 +207,14 @@ impl TEMP_OUT {
     pub const BYTES: ByteBlock = ByteBlock { start: 0, len: 2 };
 }
 
+/// Accelerometer, temperature & gyro output registers, which are contiguous
+pub struct SENSOR_OUT;
+
+impl SENSOR_OUT {
+    pub const ADDR: u8 = 0x3b;
+    pub const BYTES: ByteBlock = ByteBlock { start: 0, len: 14 };
+}
+
 pub mod ACCEL_OUT {
     use crate::consts::ByteBlock;
 
@@ -0,0 +1,325 @@
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+use elinalgebra::F32x3;
+use i2c_tools::read_word_2c;
+
+use crate::{
+    Interrupts, Mpu6050, Mpu6050Error, FIFO_COUNT, FIFO_EN, FIFO_R_W, INT_STATUS, USER_CTRL,
+};
+use crate::{TEMP_OFFSET, TEMP_SENSITIVITY};
+
+/// Largest frame: accel, temp & gyro
+const MAX_FRAME_LEN: usize = 14;
+/// Frames are drained through a stack buffer holding this many full size frames
+const FRAMES_PER_READ: usize = 8;
+
+/// Sensors written to the FIFO on every sample
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+pub struct FifoConfig {
+    pub accel: bool,
+    pub temp: bool,
+    pub gyro: bool,
+}
+
+impl FifoConfig {
+    /// EFFECTS: Returns the bytes written to the FIFO per sample
+    pub fn frame_len(&self) -> usize {
+        self.accel as usize * 6 + self.temp as usize * 2 + self.gyro as usize * 6
+    }
+
+    fn bits(&self) -> u8 {
+        let gyro = (1 << FIFO_EN::XG_FIFO_EN_BIT)
+            | (1 << FIFO_EN::YG_FIFO_EN_BIT)
+            | (1 << FIFO_EN::ZG_FIFO_EN_BIT);
+        (self.accel as u8) << FIFO_EN::ACCEL_FIFO_EN_BIT
+            | (self.temp as u8) << FIFO_EN::TEMP_FIFO_EN_BIT
+            | if self.gyro { gyro } else { 0 }
+    }
+}
+
+/// One sample drained from the FIFO, accounting for calibrated error. Sensors not in the
+/// FIFO read zero.
+#[derive(Debug, Copy, Clone)]
+pub struct FifoSample {
+    /// Planar acceleration (Gs)
+    pub acc: F32x3,
+    /// Temperature (C)
+    pub temp: f32,
+    /// Gyroscopic acceleration (deg/s)
+    pub gyro: F32x3,
+}
+
+impl Default for FifoSample {
+    fn default() -> Self {
+        Self {
+            acc: F32x3::filled(0.0),
+            temp: 0.0,
+            gyro: F32x3::filled(0.0),
+        }
+    }
+}
+
+impl<T, E> Mpu6050<T>
+where
+    T: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Starts writing the selected sensors to the FIFO at the sample rate, discarding
+    /// anything already in it
+    pub fn enable_fifo(&mut self, config: FifoConfig) -> Result<(), Mpu6050Error<E>> {
+        self.i2c.write_byte(FIFO_EN::ADDR, config.bits())?;
+        self.fifo = config;
+        self.reset_fifo()?;
+        self.i2c
+            .write_bit(USER_CTRL::ADDR, USER_CTRL::FIFO_EN_BIT, true)?;
+        Ok(())
+    }
+
+    /// Stops writing to the FIFO
+    pub fn disable_fifo(&mut self) -> Result<(), Mpu6050Error<E>> {
+        self.i2c
+            .write_bit(USER_CTRL::ADDR, USER_CTRL::FIFO_EN_BIT, false)?;
+        self.i2c.write_byte(FIFO_EN::ADDR, 0)?;
+        self.fifo = FifoConfig::default();
+        Ok(())
+    }
+
+    /// Returns the sensors written to the FIFO
+    pub fn fifo_config(&self) -> FifoConfig {
+        self.fifo
+    }
+
+    /// Empties the FIFO. Writes are paused during the reset, which only takes effect
+    /// while the FIFO is disabled.
+    pub fn reset_fifo(&mut self) -> Result<(), Mpu6050Error<E>> {
+        const ENABLE: u8 = 1 << USER_CTRL::FIFO_EN_BIT;
+        let ctrl = self.i2c.read_byte(USER_CTRL::ADDR)?;
+        let paused = ctrl & !ENABLE;
+        self.i2c.write_byte(USER_CTRL::ADDR, paused)?;
+        self.i2c
+            .write_byte(USER_CTRL::ADDR, paused | 1 << USER_CTRL::FIFO_RESET_BIT)?;
+        if ctrl & ENABLE != 0 {
+            self.i2c.write_byte(USER_CTRL::ADDR, paused | ENABLE)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the number of bytes in the FIFO
+    pub fn read_fifo_count(&mut self) -> Result<u16, Mpu6050Error<E>> {
+        const NBYTES: usize = FIFO_COUNT::BYTES.len as usize;
+        let mut buff: [u8; NBYTES] = [0; NBYTES];
+        self.i2c.read_bytes(FIFO_COUNT::ADDR, &mut buff)?;
+        Ok(u16::from_be_bytes(buff))
+    }
+
+    /// Reads whether the FIFO has overflowed since the interrupt status was last read.
+    /// Reading INT_STATUS clears every flag and a latched INT pin, so the other flags are
+    /// kept for read_interrupt_status.
+    pub fn read_fifo_overflow(&mut self) -> Result<bool, Mpu6050Error<E>> {
+        let reg = self.i2c.read_byte(INT_STATUS::ADDR)?;
+        let status = Interrupts::from_bits_truncate(reg);
+        self.interrupts |= status - Interrupts::FIFO_OVERFLOW;
+        Ok(status.contains(Interrupts::FIFO_OVERFLOW))
+    }
+
+    /// Drains as many whole samples as fit into dst, returning how many were read. If the
+    /// FIFO filled up, samples were lost and frames may be misaligned, so it is reset and
+    /// FifoOverflow is returned.
+    pub fn read_fifo(&mut self, dst: &mut [FifoSample]) -> Result<usize, Mpu6050Error<E>> {
+        let frame_len = self.fifo.frame_len();
+        if frame_len == 0 {
+            return Ok(0);
+        }
+        let count = self.read_fifo_count()?;
+        if count >= FIFO_R_W::SIZE || self.read_fifo_overflow()? {
+            self.reset_fifo()?;
+            return Err(Mpu6050Error::FifoOverflow);
+        }
+
+        let frames = dst.len().min(count as usize / frame_len);
+        let mut buf = [0u8; MAX_FRAME_LEN * FRAMES_PER_READ];
+        let per_read = buf.len() / frame_len;
+        let mut read = 0;
+        while read < frames {
+            let n = per_read.min(frames - read);
+            self.i2c
+                .read_bytes(FIFO_R_W::ADDR, &mut buf[..n * frame_len])?;
+            for (frame, sample) in buf
+                .chunks_exact(frame_len)
+                .take(n)
+                .zip(dst[read..].iter_mut())
+            {
+                *sample = self.parse_fifo_frame(frame);
+            }
+            read += n;
+        }
+        Ok(frames)
+    }
+
+    /// Parses a frame, which holds the enabled sensors in register order
+    fn parse_fifo_frame(&self, frame: &[u8]) -> FifoSample {
+        let mut sample = FifoSample::default();
+        let mut words = frame.chunks_exact(2).map(|w| read_word_2c(w) as f32);
+        let mut next = || words.next().unwrap_or(0.0);
+        if self.fifo.accel {
+            sample.acc = F32x3::new(next(), next(), next());
+            sample.acc /= self.acc_sensitivity;
+            sample.acc -= self.acc_err;
+        }
+        if self.fifo.temp {
+            sample.temp = next() / TEMP_SENSITIVITY + TEMP_OFFSET;
+        }
+        if self.fifo.gyro {
+            sample.gyro = F32x3::new(next(), next(), next());
+            sample.gyro /= self.gyro_sensitivity;
+            sample.gyro -= self.gyro_err;
+        }
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockI2c;
+    use crate::TEMP_OFFSET;
+
+    const ALL: FifoConfig = FifoConfig {
+        accel: true,
+        temp: true,
+        gyro: true,
+    };
+
+    fn assert_close(a: &F32x3, b: &F32x3) {
+        let err = *a - *b;
+        assert!(
+            err.x.abs() < 1e-4 && err.y.abs() < 1e-4 && err.z.abs() < 1e-4,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    /// Accel (1, -0.5, 0) G then gyro (1, -2, 250) deg/s at the power-on ranges
+    const ACCEL_GYRO_FRAME: [u8; 12] = [
+        0x40, 0x00, 0xe0, 0x00, 0x00, 0x00, 0x00, 0x83, 0xfe, 0xfa, 0x7f, 0xee,
+    ];
+
+    #[test]
+    fn frame_len_counts_enabled_sensors() {
+        assert_eq!(FifoConfig::default().frame_len(), 0);
+        let accel = FifoConfig {
+            accel: true,
+            ..FifoConfig::default()
+        };
+        assert_eq!(accel.frame_len(), 6);
+        let temp = FifoConfig {
+            temp: true,
+            ..FifoConfig::default()
+        };
+        assert_eq!(temp.frame_len(), 2);
+        let gyro = FifoConfig {
+            gyro: true,
+            ..FifoConfig::default()
+        };
+        assert_eq!(gyro.frame_len(), 6);
+        assert_eq!(ALL.frame_len(), 14);
+    }
+
+    #[test]
+    fn parse_frame_scales_and_removes_error() {
+        let mut i2c = MockI2c::new();
+        let mut mpu = Mpu6050::new(&mut i2c);
+        mpu.fifo = FifoConfig { temp: false, ..ALL };
+        mpu.acc_err = F32x3::new(0.0, 0.0, 0.5);
+        mpu.gyro_err = F32x3::new(1.0, 0.0, 0.0);
+        let sample = mpu.parse_fifo_frame(&ACCEL_GYRO_FRAME);
+        assert_close(&sample.acc, &F32x3::new(1.0, -0.5, -0.5));
+        assert_close(&sample.gyro, &F32x3::new(0.0, -2.0, 250.0));
+        assert_eq!(sample.temp, 0.0);
+    }
+
+    #[test]
+    fn parse_frame_reads_sensors_in_register_order() {
+        let mut i2c = MockI2c::new();
+        let mut mpu = Mpu6050::new(&mut i2c);
+        mpu.fifo = ALL;
+        // Accel, then temperature 0x0154 = 340 counts = 1 C above the offset, then gyro
+        let mut frame = [0u8; 14];
+        frame[..6].copy_from_slice(&ACCEL_GYRO_FRAME[..6]);
+        frame[6..8].copy_from_slice(&[0x01, 0x54]);
+        frame[8..].copy_from_slice(&ACCEL_GYRO_FRAME[6..]);
+        let sample = mpu.parse_fifo_frame(&frame);
+        assert_close(&sample.acc, &F32x3::new(1.0, -0.5, 0.0));
+        assert!((sample.temp - (TEMP_OFFSET + 1.0)).abs() < 1e-4);
+        assert_close(&sample.gyro, &F32x3::new(1.0, -2.0, 250.0));
+
+        // Gyro alone starts at the first word
+        mpu.fifo = FifoConfig {
+            gyro: true,
+            ..FifoConfig::default()
+        };
+        let sample = mpu.parse_fifo_frame(&ACCEL_GYRO_FRAME[6..]);
+        assert_close(&sample.acc, &F32x3::filled(0.0));
+        assert_close(&sample.gyro, &F32x3::new(1.0, -2.0, 250.0));
+    }
+
+    #[test]
+    fn reset_pauses_fifo_writes() {
+        let mut i2c = MockI2c::new();
+        i2c.regs[USER_CTRL::ADDR as usize] = 1 << USER_CTRL::FIFO_EN_BIT;
+        Mpu6050::new(&mut i2c).reset_fifo().unwrap();
+        assert_eq!(
+            i2c.writes(),
+            &[
+                (USER_CTRL::ADDR, 0),
+                (USER_CTRL::ADDR, 1 << USER_CTRL::FIFO_RESET_BIT),
+                (USER_CTRL::ADDR, 1 << USER_CTRL::FIFO_EN_BIT),
+            ]
+        );
+
+        // A disabled FIFO stays disabled
+        let mut i2c = MockI2c::new();
+        Mpu6050::new(&mut i2c).reset_fifo().unwrap();
+        assert_eq!(
+            i2c.regs[USER_CTRL::ADDR as usize],
+            1 << USER_CTRL::FIFO_RESET_BIT
+        );
+    }
+
+    #[test]
+    fn read_fifo_keeps_other_interrupts() {
+        let mut i2c = MockI2c::new();
+        i2c.fifo[..12].copy_from_slice(&ACCEL_GYRO_FRAME);
+        i2c.fifo[12..24].copy_from_slice(&ACCEL_GYRO_FRAME);
+        // Two frames and a partial third
+        i2c.regs[FIFO_COUNT::ADDR as usize + 1] = 30;
+        let flags = Interrupts::MOTION | Interrupts::DATA_READY;
+        i2c.regs[INT_STATUS::ADDR as usize] = flags.bits();
+        let mut mpu = Mpu6050::new(&mut i2c);
+        mpu.fifo = FifoConfig { temp: false, ..ALL };
+        let mut samples = [FifoSample::default(); 4];
+        assert_eq!(mpu.read_fifo(&mut samples).unwrap(), 2);
+        assert_close(&samples[1].gyro, &F32x3::new(1.0, -2.0, 250.0));
+        assert_eq!(mpu.read_interrupt_status().unwrap(), flags);
+        assert_eq!(mpu.read_interrupt_status().unwrap(), Interrupts::empty());
+    }
+
+    #[test]
+    fn read_fifo_resets_on_overflow() {
+        let mut i2c = MockI2c::new();
+        i2c.regs[INT_STATUS::ADDR as usize] = Interrupts::FIFO_OVERFLOW.bits();
+        i2c.regs[FIFO_COUNT::ADDR as usize + 1] = 12;
+        let mut mpu = Mpu6050::new(&mut i2c);
+        mpu.fifo = ALL;
+        let mut samples = [FifoSample::default(); 4];
+        assert!(matches!(
+            mpu.read_fifo(&mut samples),
+            Err(Mpu6050Error::FifoOverflow)
+        ));
+        assert_eq!(mpu.read_interrupt_status().unwrap(), Interrupts::empty());
+        assert_eq!(
+            i2c.regs[USER_CTRL::ADDR as usize],
+            1 << USER_CTRL::FIFO_RESET_BIT
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use bitflags::bitflags;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+use crate::{Mpu6050, Mpu6050Error};
+use crate::{INT_ENABLE, INT_PIN_CFG, INT_STATUS, MOT_DUR, MOT_THR, ZRMOT_DUR, ZRMOT_THR};
+
+bitflags! {
+    /// Interrupt sources, as enabled in INT_ENABLE and reported by INT_STATUS
+    pub struct Interrupts: u8 {
+        /// Acceleration exceeded the motion threshold
+        const MOTION = 1 << INT_STATUS::MOT_INT_BIT;
+        /// Acceleration stayed below the zero motion threshold
+        const ZERO_MOTION = 1 << INT_STATUS::ZMOT_INT_BIT;
+        const FIFO_OVERFLOW = 1 << INT_STATUS::FIFO_OFLOW_INT_BIT;
+        /// A new sample was written to the data registers
+        const DATA_READY = 1 << INT_STATUS::DATA_RDY_INT_BIT;
+    }
+}
+
+/// Electrical behaviour of the INT pin. Defaults match the power-on state.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+pub struct InterruptPinConfig {
+    /// Whether the pin is driven low rather than high when asserted
+    pub active_low: bool,
+    /// Whether the pin is open drain rather than push-pull
+    pub open_drain: bool,
+    /// Whether the pin stays asserted until cleared rather than pulsing for 50 us
+    pub latch: bool,
+    /// Whether any register read clears the interrupt, rather than only reading the status
+    pub clear_on_any_read: bool,
+}
+
+impl InterruptPinConfig {
+    fn bits(&self) -> u8 {
+        (self.active_low as u8) << INT_PIN_CFG::INT_LEVEL_BIT
+            | (self.open_drain as u8) << INT_PIN_CFG::INT_OPEN_BIT
+            | (self.latch as u8) << INT_PIN_CFG::LATCH_INT_EN_BIT
+            | (self.clear_on_any_read as u8) << INT_PIN_CFG::INT_RD_CLEAR_BIT
+    }
+}
+
+impl<T, E> Mpu6050<T>
+where
+    T: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Sets the INT pin level, drive & latching
+    pub fn set_interrupt_pin_config(
+        &mut self,
+        config: &InterruptPinConfig,
+    ) -> Result<(), Mpu6050Error<E>> {
+        const MASK: u8 = (1 << INT_PIN_CFG::INT_LEVEL_BIT)
+            | (1 << INT_PIN_CFG::INT_OPEN_BIT)
+            | (1 << INT_PIN_CFG::LATCH_INT_EN_BIT)
+            | (1 << INT_PIN_CFG::INT_RD_CLEAR_BIT);
+        let reg = self.i2c.read_byte(INT_PIN_CFG::ADDR)?;
+        self.i2c
+            .write_byte(INT_PIN_CFG::ADDR, reg & !MASK | config.bits())?;
+        Ok(())
+    }
+
+    /// Sets which sources assert the INT pin
+    pub fn set_interrupts(&mut self, interrupts: Interrupts) -> Result<(), Mpu6050Error<E>> {
+        self.i2c.write_byte(INT_ENABLE::ADDR, interrupts.bits())?;
+        Ok(())
+    }
+
+    /// Reads which sources assert the INT pin
+    pub fn read_interrupts(&mut self) -> Result<Interrupts, Mpu6050Error<E>> {
+        let reg = self.i2c.read_byte(INT_ENABLE::ADDR)?;
+        Ok(Interrupts::from_bits_truncate(reg))
+    }
+
+    /// Reads which interrupts have occurred since the last call, clearing them all.
+    /// Includes those seen while checking the FIFO for overflow.
+    pub fn read_interrupt_status(&mut self) -> Result<Interrupts, Mpu6050Error<E>> {
+        let reg = self.i2c.read_byte(INT_STATUS::ADDR)?;
+        let status = Interrupts::from_bits_truncate(reg) | self.interrupts;
+        self.interrupts = Interrupts::empty();
+        Ok(status)
+    }
+
+    /// Sets the acceleration (Gs) that must be exceeded for duration_ms to raise a motion
+    /// interrupt
+    pub fn set_motion_detection(
+        &mut self,
+        threshold: f32,
+        duration_ms: u16,
+    ) -> Result<(), Mpu6050Error<E>> {
+        self.i2c.write_byte(
+            MOT_THR::ADDR,
+            Self::threshold_counts(threshold, MOT_THR::LSB),
+        )?;
+        self.i2c.write_byte(
+            MOT_DUR::ADDR,
+            Self::duration_counts(duration_ms, MOT_DUR::LSB_MS),
+        )?;
+        Ok(())
+    }
+
+    /// Sets the acceleration (Gs) that must not be exceeded for duration_ms to raise a zero
+    /// motion interrupt
+    pub fn set_zero_motion_detection(
+        &mut self,
+        threshold: f32,
+        duration_ms: u16,
+    ) -> Result<(), Mpu6050Error<E>> {
+        self.i2c.write_byte(
+            ZRMOT_THR::ADDR,
+            Self::threshold_counts(threshold, ZRMOT_THR::LSB),
+        )?;
+        self.i2c.write_byte(
+            ZRMOT_DUR::ADDR,
+            Self::duration_counts(duration_ms, ZRMOT_DUR::LSB_MS),
+        )?;
+        Ok(())
+    }
+
+    fn threshold_counts(threshold: f32, lsb: f32) -> u8 {
+        (threshold / lsb).clamp(0.0, u8::MAX as f32) as u8
+    }
+
+    fn duration_counts(duration_ms: u16, lsb_ms: u16) -> u8 {
+        (duration_ms / lsb_ms).min(u8::MAX as u16) as u8
+    }
+}
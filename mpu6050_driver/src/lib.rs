@@ -53,13 +53,20 @@ use embedded_hal::blocking::{
     delay::DelayMs,
     i2c::{Write, WriteRead},
 };
+pub use fifo::{FifoConfig, FifoSample};
+pub use interrupt::{InterruptPinConfig, Interrupts};
+#[allow(unused_imports)] // inherent f32 methods shadow it in host tests
 use micromath::F32Ext;
+pub use self_test::{AxisSelfTest, SelfTestReport};
 
 use elinalgebra::{F32x2, F32x3};
 use i2c_tools::{read_word_2c, I2cDevice, I2cWrapperError};
 
 /// Constants for MPU6050 addresses & values
 pub mod consts;
+mod fifo;
+mod interrupt;
+mod self_test;
 
 /// The mpu6050 driver struct
 pub struct Mpu6050<T> {
@@ -69,6 +76,15 @@ pub struct Mpu6050<T> {
     acc_sensitivity: f32,
     /// Gyroscope sensitivity
     gyro_sensitivity: f32,
+    /// Digital low-pass filter setting
+    dlpf: DlpfBandwidth,
+    /// Sample rate divider
+    sample_rate_div: u8,
+    /// Sensors written to the FIFO
+    fifo: FifoConfig,
+    /// Interrupts read from INT_STATUS while checking the FIFO, which reading clears,
+    /// kept for read_interrupt_status
+    interrupts: Interrupts,
     /// Gyroscopic acceleration error offset (degrees/sec)
     pub gyro_err: F32x3,
     /// Planar acceleration angle error offset (degrees/sec)
@@ -87,6 +103,10 @@ where
             i2c: I2cDevice::new(i2c, MPU_ADDR),
             acc_sensitivity: AccelRange::G2.sensitivity(),
             gyro_sensitivity: GyroRange::D250.sensitivity(),
+            dlpf: DlpfBandwidth::Hz260,
+            sample_rate_div: 0,
+            fifo: FifoConfig::default(),
+            interrupts: Interrupts::empty(),
             gyro_err: F32x3::filled(0.0),
             acc_angle_err: F32x2::filled(0.0),
             acc_err: F32x3::filled(0.0),
@@ -108,13 +128,24 @@ where
         Ok(())
     }
 
-    /// Initializes the mpu
+    /// Initializes the mpu with the power-on configuration
     pub fn init<D: DelayMs<u8>>(&mut self, delay: &mut D) -> Result<(), Mpu6050Error<E>> {
+        self.init_with_config(delay, &Mpu6050Config::default())
+    }
+
+    /// Initializes the mpu with the given ranges, filter & sample rate
+    pub fn init_with_config<D: DelayMs<u8>>(
+        &mut self,
+        delay: &mut D,
+        config: &Mpu6050Config,
+    ) -> Result<(), Mpu6050Error<E>> {
         self.reset_device(delay)?;
         self.wake(delay)?;
         self.i2c.whoami(WHO_AM_I::ADDR, WHO_AM_I::EXP_RESULT)?;
-        self.set_accel_range(AccelRange::G2)?;
-        self.set_gyro_range(GyroRange::D250)?;
+        self.set_accel_range(config.accel_range)?;
+        self.set_gyro_range(config.gyro_range)?;
+        self.set_dlpf(config.dlpf)?;
+        self.set_sample_rate_divider(config.sample_rate_div)?;
         Ok(())
     }
 
@@ -127,6 +158,34 @@ where
         Ok((raw_tmp / TEMP_SENSITIVITY) + TEMP_OFFSET)
     }
 
+    /// Reads planar acceleration, temperature & gyroscopic acceleration in one transaction
+    /// so all come from the same sample, accounting for calibrated error
+    pub fn read_all(&mut self, timestamp: Option<u64>) -> Result<ImuSample, Mpu6050Error<E>> {
+        let mut sample = self.read_all_raw(timestamp)?;
+        sample.acc -= self.acc_err;
+        sample.gyro -= self.gyro_err;
+        Ok(sample)
+    }
+
+    /// Reads planar acceleration, temperature & gyroscopic acceleration in one transaction
+    /// so all come from the same sample
+    pub fn read_all_raw(&mut self, timestamp: Option<u64>) -> Result<ImuSample, Mpu6050Error<E>> {
+        const NBYTES: usize = SENSOR_OUT::BYTES.len as usize;
+        let mut buff: [u8; NBYTES] = [0; NBYTES];
+        self.i2c.read_bytes(SENSOR_OUT::ADDR, &mut buff)?;
+        let word = |i: usize| read_word_2c(&buff[i * 2..i * 2 + 2]) as f32;
+        let mut acc = F32x3::new(word(0), word(1), word(2));
+        acc /= self.acc_sensitivity;
+        let mut gyro = F32x3::new(word(4), word(5), word(6));
+        gyro /= self.gyro_sensitivity;
+        Ok(ImuSample {
+            acc,
+            temp: word(3) / TEMP_SENSITIVITY + TEMP_OFFSET,
+            gyro,
+            timestamp,
+        })
+    }
+
     /// Enables or disables temperature measurement
     pub fn set_temp_enabled(&mut self, enabled: bool) -> Result<(), Mpu6050Error<E>> {
         self.i2c
@@ -152,6 +211,52 @@ where
         Ok(())
     }
 
+    /// Sets the digital low-pass filter bandwidth, which also sets the gyro output rate
+    pub fn set_dlpf(&mut self, dlpf: DlpfBandwidth) -> Result<(), Mpu6050Error<E>> {
+        const BITS: BitBlock = CONFIG::DLPF_CFG_BITS;
+        self.i2c
+            .write_bits(CONFIG::ADDR, BITS.start, BITS.len, dlpf as u8)?;
+        self.dlpf = dlpf;
+        Ok(())
+    }
+
+    /// Returns the digital low-pass filter bandwidth
+    pub fn dlpf(&self) -> DlpfBandwidth {
+        self.dlpf
+    }
+
+    /// Sets the sample rate to the gyro output rate / (1 + div)
+    pub fn set_sample_rate_divider(&mut self, div: u8) -> Result<(), Mpu6050Error<E>> {
+        self.i2c.write_byte(SMPLRT_DIV::ADDR, div)?;
+        self.sample_rate_div = div;
+        Ok(())
+    }
+
+    /// Sets the sample rate (Hz) as close to rate_hz as the divider allows, returning the
+    /// rate set
+    pub fn set_sample_rate(&mut self, rate_hz: f32) -> Result<f32, Mpu6050Error<E>> {
+        let div = self.dlpf.gyro_output_rate_hz() / rate_hz - 1.0;
+        let div = (div + 0.5).clamp(0.0, u8::MAX as f32) as u8;
+        self.set_sample_rate_divider(div)?;
+        Ok(self.sample_rate())
+    }
+
+    /// Returns the sample rate divider
+    pub fn sample_rate_divider(&self) -> u8 {
+        self.sample_rate_div
+    }
+
+    /// Returns the rate (Hz) new gyro samples are written to the data registers & FIFO
+    pub fn sample_rate(&self) -> f32 {
+        self.dlpf.gyro_output_rate_hz() / (1.0 + self.sample_rate_div as f32)
+    }
+
+    /// Returns the rate (Hz) new accelerometer samples are produced, which never exceeds
+    /// 1 kHz
+    pub fn accel_sample_rate(&self) -> f32 {
+        self.sample_rate().min(DlpfBandwidth::ACCEL_OUTPUT_RATE_HZ)
+    }
+
     /// Calculates all error offset values. Device should be placed flat and not moving.
     pub fn calculate_all_imu_error(&mut self, iters: i32) -> Result<(), Mpu6050Error<E>> {
         self.calculate_imu_acc_angle_error(iters)?;
@@ -265,8 +370,8 @@ where
 
     /// Calculates roll & pitch from acceleration data
     fn calc_acc_angle_raw(acc: &F32x3, dst: &mut F32x2) {
-        dst.x = (acc.y / (acc.x * acc.x + acc.z * acc.z).sqrt()).atan() * 180.0 / PI;
-        dst.y = (-acc.x / (acc.y * acc.y + acc.z * acc.z).sqrt()).atan() * 180.0 / PI;
+        dst.x = (acc.y / F32x2::new(acc.x, acc.z).length()).atan() * 180.0 / PI;
+        dst.y = (-acc.x / F32x2::new(acc.y, acc.z).length()).atan() * 180.0 / PI;
     }
 
     /// Calculates roll & pitch from acceleration data, storing it in dst
@@ -292,11 +397,26 @@ where
     }
 }
 
+/// Readings from every sensor taken at the same instant
+#[derive(Debug, Copy, Clone)]
+pub struct ImuSample {
+    /// Planar acceleration (Gs)
+    pub acc: F32x3,
+    /// Temperature (C)
+    pub temp: f32,
+    /// Gyroscopic acceleration (deg/s)
+    pub gyro: F32x3,
+    /// Time the sample was read, in caller-defined units
+    pub timestamp: Option<u64>,
+}
+
 #[derive(Debug)]
 pub enum Mpu6050Error<T> {
     I2c(T),
     InvalidChipId(u8),
     NoAck,
+    /// The FIFO filled up and was reset
+    FifoOverflow,
 }
 
 impl<E> From<I2cWrapperError<E>> for Mpu6050Error<E> {
@@ -308,6 +428,76 @@ impl<E> From<I2cWrapperError<E>> for Mpu6050Error<E> {
     }
 }
 
+/// Measurement ranges, filtering & sample rate applied by init
+#[derive(Debug, Copy, Clone)]
+pub struct Mpu6050Config {
+    pub accel_range: AccelRange,
+    pub gyro_range: GyroRange,
+    pub dlpf: DlpfBandwidth,
+    /// Sample rate = gyro output rate / (1 + sample_rate_div)
+    pub sample_rate_div: u8,
+}
+
+impl Default for Mpu6050Config {
+    fn default() -> Self {
+        Self {
+            accel_range: AccelRange::G2,
+            gyro_range: GyroRange::D250,
+            dlpf: DlpfBandwidth::Hz260,
+            sample_rate_div: 0,
+        }
+    }
+}
+
+/// Digital low-pass filter setting, named by its approximate accelerometer bandwidth.
+/// Lower bandwidths reject more vibration at the cost of delay.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum DlpfBandwidth {
+    Hz260 = 0,
+    Hz184 = 1,
+    Hz94 = 2,
+    Hz44 = 3,
+    Hz21 = 4,
+    Hz10 = 5,
+    Hz5 = 6,
+}
+
+impl DlpfBandwidth {
+    pub const ACCEL_OUTPUT_RATE_HZ: f32 = 1000.0;
+
+    pub fn accel_bandwidth_hz(&self) -> f32 {
+        match &self {
+            DlpfBandwidth::Hz260 => 260.0,
+            DlpfBandwidth::Hz184 => 184.0,
+            DlpfBandwidth::Hz94 => 94.0,
+            DlpfBandwidth::Hz44 => 44.0,
+            DlpfBandwidth::Hz21 => 21.0,
+            DlpfBandwidth::Hz10 => 10.0,
+            DlpfBandwidth::Hz5 => 5.0,
+        }
+    }
+
+    pub fn gyro_bandwidth_hz(&self) -> f32 {
+        match &self {
+            DlpfBandwidth::Hz260 => 256.0,
+            DlpfBandwidth::Hz184 => 188.0,
+            DlpfBandwidth::Hz94 => 98.0,
+            DlpfBandwidth::Hz44 => 42.0,
+            DlpfBandwidth::Hz21 => 20.0,
+            DlpfBandwidth::Hz10 => 10.0,
+            DlpfBandwidth::Hz5 => 5.0,
+        }
+    }
+
+    /// Returns the gyro output rate (Hz) the sample rate divider divides
+    pub fn gyro_output_rate_hz(&self) -> f32 {
+        match &self {
+            DlpfBandwidth::Hz260 => 8000.0,
+            _ => 1000.0,
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum AccelRange {
     G2 = 0,
@@ -344,4 +534,147 @@ impl GyroRange {
             GyroRange::D2000 => 16.4,
         }
     }
+
+    /// Returns the largest rate (deg/s) that can be measured, where readings saturate
+    pub fn full_scale(&self) -> f32 {
+        match &self {
+            GyroRange::D250 => 250.0,
+            GyroRange::D500 => 500.0,
+            GyroRange::D1000 => 1000.0,
+            GyroRange::D2000 => 2000.0,
+        }
+    }
+}
+
+/// Register file standing in for the chip in host tests. Reading INT_STATUS clears it and
+/// FIFO_R_W reads stream out of fifo.
+#[cfg(test)]
+mod mock {
+    use embedded_hal::blocking::delay::DelayMs;
+    use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+    use crate::{FIFO_R_W, INT_STATUS};
+
+    pub struct NoDelay;
+
+    impl DelayMs<u8> for NoDelay {
+        fn delay_ms(&mut self, _ms: u8) {}
+    }
+
+    pub struct MockI2c {
+        pub regs: [u8; 128],
+        /// Register whose reads fail
+        pub failing_reg: Option<u8>,
+        pub fifo: [u8; 64],
+        fifo_pos: usize,
+        /// Register writes in order as (register, value)
+        pub writes: [(u8, u8); 16],
+        pub write_count: usize,
+    }
+
+    impl MockI2c {
+        pub fn new() -> Self {
+            Self {
+                regs: [0; 128],
+                failing_reg: None,
+                fifo: [0; 64],
+                fifo_pos: 0,
+                writes: [(0, 0); 16],
+                write_count: 0,
+            }
+        }
+
+        /// EFFECTS: Returns the register writes made so far
+        pub fn writes(&self) -> &[(u8, u8)] {
+            &self.writes[..self.write_count]
+        }
+    }
+
+    impl Write for &mut MockI2c {
+        type Error = ();
+
+        fn write(&mut self, _addr: u8, bytes: &[u8]) -> Result<(), ()> {
+            let reg = bytes[0] as usize;
+            self.regs[reg..reg + bytes.len() - 1].copy_from_slice(&bytes[1..]);
+            for (i, value) in bytes[1..].iter().enumerate() {
+                if self.write_count < self.writes.len() {
+                    self.writes[self.write_count] = ((reg + i) as u8, *value);
+                    self.write_count += 1;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl WriteRead for &mut MockI2c {
+        type Error = ();
+
+        fn write_read(&mut self, _addr: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+            let reg = bytes[0];
+            if self.failing_reg == Some(reg) {
+                return Err(());
+            }
+            if reg == FIFO_R_W::ADDR {
+                let end = self.fifo_pos + buffer.len();
+                buffer.copy_from_slice(&self.fifo[self.fifo_pos..end]);
+                self.fifo_pos = end;
+                return Ok(());
+            }
+            let reg = reg as usize;
+            buffer.copy_from_slice(&self.regs[reg..reg + buffer.len()]);
+            if reg == INT_STATUS::ADDR as usize {
+                self.regs[reg] = 0;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockI2c;
+
+    #[test]
+    fn set_dlpf_only_writes_dlpf_cfg() {
+        let mut i2c = MockI2c::new();
+        i2c.regs[CONFIG::ADDR as usize] = 0b0100_1110;
+        i2c.regs[GYRO_CONFIG::ADDR as usize] = 0b0001_1011;
+        let mut mpu = Mpu6050::new(&mut i2c);
+        mpu.set_dlpf(DlpfBandwidth::Hz44).unwrap();
+        assert_eq!(mpu.dlpf(), DlpfBandwidth::Hz44);
+        assert_eq!(i2c.regs[CONFIG::ADDR as usize], 0b0100_1011);
+        assert_eq!(i2c.regs[GYRO_CONFIG::ADDR as usize], 0b0001_1011);
+    }
+
+    #[test]
+    fn set_sample_rate_rounds_to_nearest_divider() {
+        let mut i2c = MockI2c::new();
+        let mut mpu = Mpu6050::new(&mut i2c);
+        // 8 kHz gyro output without the DLPF
+        assert_eq!(mpu.set_sample_rate(1000.0).unwrap(), 1000.0);
+        assert_eq!(mpu.sample_rate_divider(), 7);
+        // 8000 / 3000 - 1 = 1.67 rounds up to 2
+        assert!((mpu.set_sample_rate(3000.0).unwrap() - 2666.667).abs() < 1e-2);
+        assert_eq!(mpu.sample_rate_divider(), 2);
+        // 1 kHz gyro output with the DLPF: 1000 / 450 - 1 = 1.22 rounds down to 1
+        mpu.set_dlpf(DlpfBandwidth::Hz44).unwrap();
+        assert_eq!(mpu.set_sample_rate(450.0).unwrap(), 500.0);
+        assert_eq!(mpu.sample_rate_divider(), 1);
+        assert_eq!(mpu.accel_sample_rate(), 500.0);
+        assert_eq!(i2c.regs[SMPLRT_DIV::ADDR as usize], 1);
+    }
+
+    #[test]
+    fn set_sample_rate_clamps_divider() {
+        let mut i2c = MockI2c::new();
+        let mut mpu = Mpu6050::new(&mut i2c);
+        // Faster than the output rate clamps to no division
+        assert_eq!(mpu.set_sample_rate(20_000.0).unwrap(), 8000.0);
+        assert_eq!(mpu.sample_rate_divider(), 0);
+        assert_eq!(mpu.accel_sample_rate(), 1000.0);
+        // Slower than the largest divider allows clamps to 255
+        assert_eq!(mpu.set_sample_rate(1.0).unwrap(), 31.25);
+        assert_eq!(mpu.sample_rate_divider(), 255);
+    }
 }
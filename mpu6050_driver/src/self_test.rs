@@ -0,0 +1,295 @@
+use embedded_hal::blocking::{
+    delay::DelayMs,
+    i2c::{Write, WriteRead},
+};
+#[allow(unused_imports)] // inherent f32 methods shadow it in host tests
+use micromath::F32Ext;
+
+use elinalgebra::F32x3;
+
+use crate::{AccelRange, BitBlock, GyroRange, Mpu6050, Mpu6050Error};
+use crate::{ACCEL_CONFIG, ACCEL_OUT, GYRO_CONFIG, GYRO_OUT, SELF_TEST};
+
+/// Time (ms) for readings to settle after changing the configuration
+const SETTLE_MS: u8 = 250;
+/// Readings averaged with and without self-test
+const SAMPLES: u8 = 10;
+
+/// Self-test result of one sensor axis, in raw counts
+#[derive(Debug, Copy, Clone)]
+pub struct AxisSelfTest {
+    /// Change in output when self-test is enabled
+    pub response: f32,
+    /// Response expected from the factory self-test code
+    pub factory_trim: f32,
+    /// Deviation (%) of the response from factory trim
+    pub deviation: f32,
+    pub passed: bool,
+}
+
+impl AxisSelfTest {
+    fn new(response: f32, factory_trim: f32) -> Self {
+        // A zero code means the axis has no factory trim to compare against
+        let deviation = if factory_trim == 0.0 {
+            f32::INFINITY
+        } else {
+            (response - factory_trim) / factory_trim * 100.0
+        };
+        Self {
+            response,
+            factory_trim,
+            deviation,
+            passed: deviation.abs() <= SELF_TEST::TOLERANCE,
+        }
+    }
+}
+
+/// Per-axis self-test results, in x, y, z order
+#[derive(Debug, Copy, Clone)]
+pub struct SelfTestReport {
+    pub accel: [AxisSelfTest; 3],
+    pub gyro: [AxisSelfTest; 3],
+}
+
+impl SelfTestReport {
+    /// Returns a report failing every axis, for when the self-test could not run
+    pub fn failed() -> Self {
+        Self {
+            accel: [AxisSelfTest::new(0.0, 0.0); 3],
+            gyro: [AxisSelfTest::new(0.0, 0.0); 3],
+        }
+    }
+
+    /// Compares the change in raw counts with self-test enabled against the factory trim
+    /// of the SELF_TEST_X, Y, Z & A codes
+    fn new(acc_response: &F32x3, gyro_response: &F32x3, codes: &[u8; 4]) -> Self {
+        let acc_response = [acc_response.x, acc_response.y, acc_response.z];
+        let gyro_response = [gyro_response.x, gyro_response.y, gyro_response.z];
+        let mut report = Self::failed();
+        for axis in 0..3 {
+            report.accel[axis] = AxisSelfTest::new(
+                acc_response[axis],
+                accel_factory_trim(accel_code(codes, axis)),
+            );
+            // The y gyro self-test deflects in the negative direction
+            let sign = if axis == 1 { -1.0 } else { 1.0 };
+            report.gyro[axis] = AxisSelfTest::new(
+                gyro_response[axis],
+                sign * gyro_factory_trim(gyro_code(codes, axis)),
+            );
+        }
+        report
+    }
+
+    /// Returns whether every axis passed
+    pub fn passed(&self) -> bool {
+        self.accel.iter().chain(self.gyro.iter()).all(|a| a.passed)
+    }
+}
+
+impl<T, E> Mpu6050<T>
+where
+    T: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Runs the factory self-test, comparing each axis' response against its factory trim.
+    /// Device should be still. The ranges in use are restored afterwards, even on error.
+    pub fn self_test<D: DelayMs<u8>>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<SelfTestReport, Mpu6050Error<E>> {
+        let gyro_config = self.i2c.read_byte(GYRO_CONFIG::ADDR)?;
+        let accel_config = self.i2c.read_byte(ACCEL_CONFIG::ADDR)?;
+        let (acc_sensitivity, gyro_sensitivity) = (self.acc_sensitivity, self.gyro_sensitivity);
+
+        let measured = self.measure_self_test(delay);
+
+        // Restoring the saved bytes also clears the self-test bits
+        let restored = self
+            .i2c
+            .write_byte(GYRO_CONFIG::ADDR, gyro_config)
+            .and_then(|_| self.i2c.write_byte(ACCEL_CONFIG::ADDR, accel_config));
+        self.acc_sensitivity = acc_sensitivity;
+        self.gyro_sensitivity = gyro_sensitivity;
+        delay.delay_ms(SETTLE_MS);
+
+        let (acc_response, gyro_response, codes) = measured?;
+        restored?;
+        Ok(SelfTestReport::new(&acc_response, &gyro_response, &codes))
+    }
+
+    /// Returns the accel & gyro responses to self-test (counts) and the factory codes,
+    /// leaving self-test enabled
+    fn measure_self_test<D: DelayMs<u8>>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<(F32x3, F32x3, [u8; 4]), Mpu6050Error<E>> {
+        // Factory trim is specified at these ranges
+        self.set_gyro_range(GyroRange::D250)?;
+        self.set_accel_range(AccelRange::G8)?;
+        delay.delay_ms(SETTLE_MS);
+        let (acc, gyro) = self.read_average_counts(delay)?;
+
+        self.set_self_test_enabled(true)?;
+        delay.delay_ms(SETTLE_MS);
+        let (acc_st, gyro_st) = self.read_average_counts(delay)?;
+
+        let mut codes = [0; SELF_TEST::BYTES.len as usize];
+        self.i2c.read_bytes(SELF_TEST::ADDR, &mut codes)?;
+        Ok((acc_st - acc, gyro_st - gyro, codes))
+    }
+
+    /// Enables or disables self-test on every accel & gyro axis
+    fn set_self_test_enabled(&mut self, enabled: bool) -> Result<(), Mpu6050Error<E>> {
+        for bit in [
+            GYRO_CONFIG::XG_ST_BIT,
+            GYRO_CONFIG::YG_ST_BIT,
+            GYRO_CONFIG::ZG_ST_BIT,
+        ] {
+            self.i2c.write_bit(GYRO_CONFIG::ADDR, bit, enabled)?;
+        }
+        for bit in [
+            ACCEL_CONFIG::XA_ST_BIT,
+            ACCEL_CONFIG::YA_ST_BIT,
+            ACCEL_CONFIG::ZA_ST_BIT,
+        ] {
+            self.i2c.write_bit(ACCEL_CONFIG::ADDR, bit, enabled)?;
+        }
+        Ok(())
+    }
+
+    /// Reads averaged raw acceleration & gyro counts
+    fn read_average_counts<D: DelayMs<u8>>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<(F32x3, F32x3), Mpu6050Error<E>> {
+        let (mut acc, mut gyro) = (F32x3::filled(0.0), F32x3::filled(0.0));
+        let mut tmp = F32x3::filled(0.0);
+        for _ in 0..SAMPLES {
+            self.read_f32x3(ACCEL_OUT::ADDR, &mut tmp)?;
+            acc += tmp;
+            self.read_f32x3(GYRO_OUT::ADDR, &mut tmp)?;
+            gyro += tmp;
+            delay.delay_ms(2);
+        }
+        acc /= SAMPLES as f32;
+        gyro /= SAMPLES as f32;
+        Ok((acc, gyro))
+    }
+}
+
+/// Extracts the bits whose highest is at bits.start
+fn field(byte: u8, bits: BitBlock) -> u8 {
+    (byte >> (bits.start + 1 - bits.len)) & ((1 << bits.len) - 1)
+}
+
+/// Returns the 5 bit accel self-test code of an axis, split between the high bits of
+/// its SELF_TEST register and SELF_TEST_A
+fn accel_code(codes: &[u8; 4], axis: usize) -> u8 {
+    field(codes[axis], SELF_TEST::XA_TEST_HIGH_BITS) << 2
+        | (codes[3] >> SELF_TEST::A_TEST_LOW_SHIFTS[axis]) & 0b11
+}
+
+/// Returns the 5 bit gyro self-test code of an axis
+fn gyro_code(codes: &[u8; 4], axis: usize) -> u8 {
+    field(codes[axis], SELF_TEST::XG_TEST_BITS)
+}
+
+/// Returns the expected gyro response (counts at 250 deg/s) for a self-test code
+fn gyro_factory_trim(code: u8) -> f32 {
+    if code == 0 {
+        return 0.0;
+    }
+    25.0 * 131.0 * 1.046f32.powf(code as f32 - 1.0)
+}
+
+/// Returns the expected accel response (counts at 8 G) for a self-test code
+fn accel_factory_trim(code: u8) -> f32 {
+    if code == 0 {
+        return 0.0;
+    }
+    4096.0 * 0.34 * (0.92f32 / 0.34).powf((code as f32 - 1.0) / 30.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockI2c, NoDelay};
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() <= b.abs() * 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn factory_trim() {
+        assert_eq!(gyro_factory_trim(0), 0.0);
+        assert_close(gyro_factory_trim(1), 3275.0);
+        assert_close(gyro_factory_trim(11), 3275.0 * 1.046f32.powi(10));
+        assert_eq!(accel_factory_trim(0), 0.0);
+        assert_close(accel_factory_trim(1), 4096.0 * 0.34);
+        assert_close(accel_factory_trim(31), 4096.0 * 0.92);
+    }
+
+    #[test]
+    fn codes_are_unpacked() {
+        assert_eq!(field(0b101_10110, SELF_TEST::XA_TEST_HIGH_BITS), 0b101);
+        assert_eq!(field(0b101_10110, SELF_TEST::XG_TEST_BITS), 0b10110);
+        // Accel codes are the high 3 bits, then XA low bits 0b11, YA 0b01, ZA 0b10
+        let codes = [0b101_10110, 0b010_00001, 0b111_11111, 0b00_11_01_10];
+        assert_eq!(accel_code(&codes, 0), 0b10111);
+        assert_eq!(accel_code(&codes, 1), 0b01001);
+        assert_eq!(accel_code(&codes, 2), 0b11110);
+        assert_eq!(gyro_code(&codes, 0), 0b10110);
+        assert_eq!(gyro_code(&codes, 1), 0b00001);
+        assert_eq!(gyro_code(&codes, 2), 0b11111);
+    }
+
+    #[test]
+    fn deviation_from_factory_trim() {
+        let axis = AxisSelfTest::new(110.0, 100.0);
+        assert_close(axis.deviation, 10.0);
+        assert!(axis.passed);
+        let axis = AxisSelfTest::new(-86.0, -100.0);
+        assert_close(axis.deviation, -14.0);
+        assert!(axis.passed);
+        let axis = AxisSelfTest::new(115.0, 100.0);
+        assert_close(axis.deviation, 15.0);
+        assert!(!axis.passed);
+        let axis = AxisSelfTest::new(100.0, 0.0);
+        assert_eq!(axis.deviation, f32::INFINITY);
+        assert!(!axis.passed);
+    }
+
+    #[test]
+    fn report_expects_negative_y_gyro_response() {
+        let codes = [0b001_00001; 4];
+        let acc = F32x3::filled(accel_factory_trim(accel_code(&codes, 0)));
+        let trim = gyro_factory_trim(1);
+        let report = SelfTestReport::new(&acc, &F32x3::new(trim, -trim, trim), &codes);
+        assert!(report.passed());
+        let report = SelfTestReport::new(&acc, &F32x3::filled(trim), &codes);
+        assert!(!report.gyro[1].passed);
+        assert!(!report.passed());
+        assert!(!SelfTestReport::failed().passed());
+    }
+
+    #[test]
+    fn config_is_restored_when_self_test_fails() {
+        let mut i2c = MockI2c::new();
+        // 2000 deg/s & 16 G
+        i2c.regs[GYRO_CONFIG::ADDR as usize] = 0b0001_1000;
+        i2c.regs[ACCEL_CONFIG::ADDR as usize] = 0b0001_1000;
+        // Reading the factory codes fails after self-test is enabled
+        i2c.failing_reg = Some(SELF_TEST::ADDR);
+        let mut mpu = Mpu6050::new(&mut i2c);
+        mpu.set_gyro_range(GyroRange::D2000).unwrap();
+        mpu.set_accel_range(AccelRange::G16).unwrap();
+        assert!(matches!(
+            mpu.self_test(&mut NoDelay),
+            Err(Mpu6050Error::I2c(()))
+        ));
+        assert_eq!(mpu.gyro_sensitivity, GyroRange::D2000.sensitivity());
+        assert_eq!(mpu.acc_sensitivity, AccelRange::G16.sensitivity());
+        assert_eq!(i2c.regs[GYRO_CONFIG::ADDR as usize], 0b0001_1000);
+        assert_eq!(i2c.regs[ACCEL_CONFIG::ADDR as usize], 0b0001_1000);
+    }
+}
@@ -1,49 +1,145 @@
-use alloc::boxed::Box;
-
 use cortex_m::delay::Delay;
+use cortex_m::singleton;
 
 use defmt_rtt as _;
 
 use adafruit1893_driver::Adafruit1893;
+use battery_monitor::AdcBattery;
+use flight_controller::{ArmingConfig, Axis, PidGains, Stabilizer};
 use fugit::RateExtU32;
-use motor_driver::{Motor, MotorManager};
-use mpu6050_driver::Mpu6050;
+use motor_driver::{
+    CalibrationConfig, EscProtocol, Motor, MotorConfig, MotorManager, SetupMotor, SpinDirection,
+};
+use mpu6050_driver::{
+    AccelRange, DlpfBandwidth, FifoConfig, GyroRange, InterruptPinConfig, Interrupts, Mpu6050,
+    Mpu6050Config, SelfTestReport,
+};
 use panic_halt as _;
-use rp2040_hal::gpio::bank0::{Gpio0, Gpio1, Gpio14, Gpio15, Gpio2, Gpio3, Gpio8, Gpio9};
-use rp2040_hal::gpio::{FunctionI2C, Pin, PinId, PullDownDisabled, PushPullOutput};
-use rp2040_hal::pac::I2C0;
-use rp2040_hal::pwm::{FreeRunning, Pwm0, Pwm1, Slice};
+use rp2040_hal::adc::Adc;
+use rp2040_hal::gpio::bank0::{
+    Gpio0, Gpio1, Gpio14, Gpio15, Gpio2, Gpio26, Gpio27, Gpio3, Gpio8, Gpio9,
+};
+use rp2040_hal::gpio::{FloatingInput, FunctionI2C, Pin, PinId, PullDownDisabled, PushPullOutput};
+use rp2040_hal::pac::{ADC, I2C0};
+use rp2040_hal::pwm::{FreeRunning, Pwm0, Pwm1, Slice, A, B};
 use rp2040_hal::{clocks::SystemClock, I2C};
 use rp_pico::hal::prelude::*;
 use rp_pico::pac::{I2C1, RESETS};
 
+/// Motors of the quad frame, in mixer order
+pub type DroneMotors = MotorManager<&'static mut dyn SetupMotor, 4>;
+
+/// Protocol spoken by the ESCs
+pub const ESC_PROTOCOL: EscProtocol = EscProtocol::StandardPwm;
+
+/// Full throttle is held long enough to plug in the battery after confirming
+pub const ESC_CALIBRATION: CalibrationConfig = CalibrationConfig {
+    high_ms: 8000,
+    low_ms: 2000,
+};
+
+/// Battery divider on GPIO26 and current sensor on GPIO27
+pub type DroneBattery = AdcBattery<Pin<Gpio26, FloatingInput>, Pin<Gpio27, FloatingInput>>;
+
+/// ADC reference voltage (V)
+pub const ADC_REFERENCE: f32 = 3.3;
+/// Pack volts per volt at GPIO26, for a 10k/1k divider
+pub const BATTERY_VOLTAGE_SCALE: f32 = 11.0;
+/// Amps per volt at GPIO27 and the pin voltage at zero current
+pub const BATTERY_CURRENT_SCALE: f32 = 40.0;
+pub const BATTERY_CURRENT_OFFSET: f32 = 0.0;
+
+/// PWM slices & pins driving the ESCs, in mixer order
+pub struct MotorOutputs {
+    pub pwm0: Slice<Pwm0, FreeRunning>,
+    pub pwm1: Slice<Pwm1, FreeRunning>,
+    pub gpio0: Pin<Gpio0, PullDownDisabled>,
+    pub gpio1: Pin<Gpio1, PullDownDisabled>,
+    pub gpio2: Pin<Gpio2, PullDownDisabled>,
+    pub gpio3: Pin<Gpio3, PullDownDisabled>,
+}
+
 pub fn setup_motors<LED: PinId>(
     delay: &mut Delay,
     led: &mut Pin<LED, PushPullOutput>,
-    mut pwm0: Slice<Pwm0, FreeRunning>,
-    mut pwm1: Slice<Pwm1, FreeRunning>,
-    p0: Pin<Gpio0, PullDownDisabled>,
-    p1: Pin<Gpio1, PullDownDisabled>,
-    p2: Pin<Gpio2, PullDownDisabled>,
-    p3: Pin<Gpio3, PullDownDisabled>,
-) -> MotorManager {
+    outputs: MotorOutputs,
+    system_clock: &SystemClock,
+) -> DroneMotors {
+    let MotorOutputs {
+        mut pwm0,
+        mut pwm1,
+        gpio0: p0,
+        gpio1: p1,
+        gpio2: p2,
+        gpio3: p3,
+    } = outputs;
     // Configure PWM
-    pwm0.set_ph_correct();
-    pwm0.set_div_int(20u8); // 50 hz
-    pwm0.enable();
-    pwm1.set_ph_correct();
-    pwm1.set_div_int(20u8); // 50 hz
-    pwm1.enable();
-
-    let motor0 = Box::new(Motor::new_a(pwm0.channel_a, 20, p0));
-    let motor1 = Box::new(Motor::new_b(pwm0.channel_b, 20, p1));
-    let motor2 = Box::new(Motor::new_a(pwm1.channel_a, 20, p2));
-    let motor3 = Box::new(Motor::new_b(pwm1.channel_b, 20, p3));
-    let mut motor_manager = MotorManager::new([motor0, motor1, motor2, motor3]);
+    let sys_clk_hz = system_clock.freq().to_Hz();
+    ESC_PROTOCOL.configure_slice(&mut pwm0, sys_clk_hz);
+    ESC_PROTOCOL.configure_slice(&mut pwm1, sys_clk_hz);
+
+    let motor0 = Motor::new_a(pwm0.channel_a, ESC_PROTOCOL, p0);
+    let motor1 = Motor::new_b(pwm0.channel_b, ESC_PROTOCOL, p1);
+    let motor2 = Motor::new_a(pwm1.channel_a, ESC_PROTOCOL, p2);
+    let motor3 = Motor::new_b(pwm1.channel_b, ESC_PROTOCOL, p3);
+    // Motors live for the rest of the program, so they can be borrowed without a heap
+    let motor0 = singleton!(: Motor<Pwm0, FreeRunning, A> = motor0).unwrap();
+    let motor1 = singleton!(: Motor<Pwm0, FreeRunning, B> = motor1).unwrap();
+    let motor2 = singleton!(: Motor<Pwm1, FreeRunning, A> = motor2).unwrap();
+    let motor3 = singleton!(: Motor<Pwm1, FreeRunning, B> = motor3).unwrap();
+    let mut motor_manager: DroneMotors = MotorManager::new([motor0, motor1, motor2, motor3]);
+    // Quad X: rear right, front right, rear left, front left
+    let layout = [
+        ((-1.0, 1.0), SpinDirection::Clockwise),
+        ((1.0, 1.0), SpinDirection::CounterClockwise),
+        ((-1.0, -1.0), SpinDirection::CounterClockwise),
+        ((1.0, -1.0), SpinDirection::Clockwise),
+    ];
+    for (idx, (position, direction)) in layout.iter().enumerate() {
+        motor_manager.set_config(idx, MotorConfig::new(*position, *direction));
+    }
     motor_manager.setup(led, delay).unwrap();
     return motor_manager;
 }
 
+/// Fastest roll & pitch rate (deg/s) the angle loop may request
+pub const MAX_ANGLE_RATE: f32 = 200.0;
+/// Rate loop derivative low-pass cutoff (Hz), below the gyro DLPF bandwidth
+pub const RATE_D_CUTOFF_HZ: f32 = 30.0;
+
+pub fn setup_stabilizer() -> Stabilizer {
+    let angle = PidGains::new(4.0, 0.0, 0.0);
+    let rate = PidGains::new(0.002, 0.002, 0.000_05);
+    let yaw_rate = PidGains::new(0.004, 0.002, 0.0);
+    let mut stabilizer = Stabilizer::new([angle, angle], [rate, rate, yaw_rate]);
+    for axis in [Axis::Roll, Axis::Pitch].iter() {
+        stabilizer.set_max_rate(*axis, MAX_ANGLE_RATE);
+        stabilizer
+            .rate_pid_mut(*axis)
+            .set_d_lowpass(RATE_D_CUTOFF_HZ);
+    }
+    stabilizer
+}
+
+/// IMU on I2C1
+pub type DroneImu = Mpu6050<I2C<I2C1, (Pin<Gpio14, FunctionI2C>, Pin<Gpio15, FunctionI2C>)>>;
+
+/// 42 Hz gyro bandwidth sampled at 1 kHz
+pub const IMU_CONFIG: Mpu6050Config = Mpu6050Config {
+    accel_range: AccelRange::G2,
+    gyro_range: GyroRange::D250,
+    dlpf: DlpfBandwidth::Hz44,
+    sample_rate_div: 0,
+};
+
+/// Arming thresholds, with the crash rate kept inside the gyro's range
+pub fn arming_config() -> ArmingConfig {
+    ArmingConfig {
+        crash_rate: 0.9 * IMU_CONFIG.gyro_range.full_scale(),
+        ..ArmingConfig::default()
+    }
+}
+
 pub fn setup_mpu6050(
     i2c1: I2C1,
     gpio14: Pin<Gpio14, PullDownDisabled>,
@@ -51,7 +147,7 @@ pub fn setup_mpu6050(
     resets: &mut RESETS,
     system_clock: &SystemClock,
     delay: &mut Delay,
-) -> Mpu6050<I2C<I2C1, (Pin<Gpio14, FunctionI2C>, Pin<Gpio15, FunctionI2C>)>> {
+) -> (DroneImu, SelfTestReport) {
     let mut mpu = Mpu6050::new(I2C::i2c1(
         i2c1,
         gpio14.into_mode(),
@@ -60,9 +156,30 @@ pub fn setup_mpu6050(
         resets,
         system_clock.freq().to_Hz().Hz(),
     ));
-    mpu.init(delay).unwrap();
+    mpu.init_with_config(delay, &IMU_CONFIG).unwrap();
+    // A damaged or unreachable IMU is reported rather than halting, so arming can refuse it
+    let self_test = mpu
+        .self_test(delay)
+        .unwrap_or_else(|_| SelfTestReport::failed());
     mpu.calculate_all_imu_error(10).unwrap();
-    mpu
+    // The board is calibrated flat with z up, so 1 G on z is gravity, not bias
+    mpu.acc_err.z -= 1.0;
+    // Stream every sample so none are missed between loop iterations
+    mpu.enable_fifo(FifoConfig {
+        accel: true,
+        temp: false,
+        gyro: true,
+    })
+    .unwrap();
+    // INT (GPIO13) stays high from a new sample until the FIFO is next drained
+    mpu.set_interrupt_pin_config(&InterruptPinConfig {
+        latch: true,
+        ..InterruptPinConfig::default()
+    })
+    .unwrap();
+    mpu.set_interrupts(Interrupts::DATA_READY | Interrupts::FIFO_OVERFLOW)
+        .unwrap();
+    (mpu, self_test)
 }
 
 pub fn setup_adafruit1893(
@@ -82,3 +199,19 @@ pub fn setup_adafruit1893(
     ));
     a1893
 }
+
+pub fn setup_battery(
+    adc: ADC,
+    gpio26: Pin<Gpio26, PullDownDisabled>,
+    gpio27: Pin<Gpio27, PullDownDisabled>,
+    resets: &mut RESETS,
+) -> (Adc, DroneBattery) {
+    let adc = Adc::new(adc, resets);
+    let battery = AdcBattery::new(gpio26.into_mode(), ADC_REFERENCE, BATTERY_VOLTAGE_SCALE)
+        .with_current(
+            gpio27.into_mode(),
+            BATTERY_CURRENT_SCALE,
+            BATTERY_CURRENT_OFFSET,
+        );
+    (adc, battery)
+}
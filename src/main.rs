@@ -11,6 +11,7 @@ use cortex_m::delay::Delay;
 
 use defmt_rtt as _;
 use embedded_alloc::Heap;
+use embedded_hal::digital::v2::InputPin;
 
 use panic_halt as _;
 
@@ -23,8 +24,16 @@ use usb_device::{class_prelude::*, prelude::*};
 use usbd_serial::{SerialPort, USB_CLASS_CDC};
 
 use adafruit1893_driver::Adafruit1893Error;
+use attitude_estimator::ComplementaryFilter;
+use battery_monitor::{Battery, BatteryConfig};
+use elinalgebra::F32x3;
+use flight_controller::{Arming, AttitudeSetpoint, AxisDemands, Mixer, SafetyInputs};
+use mpu6050_driver::FifoSample;
 
-use crate::drone::{setup_adafruit1893, setup_motors, setup_mpu6050};
+use crate::drone::{
+    arming_config, setup_adafruit1893, setup_battery, setup_motors, setup_mpu6050,
+    setup_stabilizer, DroneImu, MotorOutputs, ESC_CALIBRATION,
+};
 
 #[global_allocator]
 static HEAP: Heap = Heap::empty();
@@ -96,17 +105,16 @@ fn main() -> ! {
     let pwm_slices = Slices::new(pac.PWM, &mut pac.RESETS);
 
     let mut led = pins.led.into_push_pull_output();
-    let mut motor_manager = setup_motors(
-        &mut delay,
-        &mut led,
-        pwm_slices.pwm0,
-        pwm_slices.pwm1,
-        pins.gpio0,
-        pins.gpio1,
-        pins.gpio2,
-        pins.gpio3,
-    );
-    let mut mpu6050 = setup_mpu6050(
+    let motor_outputs = MotorOutputs {
+        pwm0: pwm_slices.pwm0,
+        pwm1: pwm_slices.pwm1,
+        gpio0: pins.gpio0,
+        gpio1: pins.gpio1,
+        gpio2: pins.gpio2,
+        gpio3: pins.gpio3,
+    };
+    let mut motor_manager = setup_motors(&mut delay, &mut led, motor_outputs, &clocks.system_clock);
+    let (mut mpu6050, imu_self_test) = setup_mpu6050(
         pac.I2C1,
         pins.gpio14,
         pins.gpio15,
@@ -114,6 +122,7 @@ fn main() -> ! {
         &clocks.system_clock,
         &mut delay,
     );
+    let imu_int = pins.gpio13.into_floating_input();
     let mut a1893 = setup_adafruit1893(
         pac.I2C0,
         pins.gpio8,
@@ -121,82 +130,276 @@ fn main() -> ! {
         &mut pac.RESETS,
         &clocks.system_clock,
     );
+    let (mut adc, mut battery_adc) =
+        setup_battery(pac.ADC, pins.gpio26, pins.gpio27, &mut pac.RESETS);
+    let mut battery = Battery::new(BatteryConfig::default());
+
+    let timer = hal::Timer::new(pac.TIMER, &mut pac.RESETS);
+    let mut estimator = ComplementaryFilter::new(0.96);
+    let mut last_update = timer.get_counter();
+    // IMU samples drained from the FIFO each iteration, spaced at the sample rate
+    let mut samples = [FifoSample::default(); 16];
+    let sample_dt = 1.0 / mpu6050.sample_rate();
+    // Latest gyro rates, held between passes that drain no samples
+    let mut gyro = F32x3::filled(0.0);
+
+    // Angle mode holds the drone level; thrust is mixed onto the motors by their layout
+    let mut stabilizer = setup_stabilizer();
+    let mut mixer = Mixer::from_geometry(
+        &motor_manager.get_positions(),
+        &motor_manager.get_yaw_signs(),
+    );
+
+    // Motors only spin while armed; requested throttle is held here until then
+    let mut arming = Arming::new(arming_config());
+    let mut throttle = 0.0f32;
+    // Command awaiting confirmation; the next byte confirms or cancels it
+    let mut pending_confirm: Option<char> = None;
 
     loop {
+        let now = timer.get_counter();
+        let dt = (now - last_update).to_micros() as f32 / 1_000_000.0;
+        last_update = now;
+
+        // Only touch the bus once the IMU signals new samples
+        let drained = if imu_int.is_high().unwrap_or(true) {
+            mpu6050.read_fifo(&mut samples)
+        } else {
+            Ok(0)
+        };
+        let imu_ok = match drained {
+            Ok(count) => {
+                for sample in samples.iter().take(count) {
+                    let acc_angle = DroneImu::calc_acc_angle(&sample.acc, &mpu6050.acc_angle_err);
+                    estimator.update(&sample.gyro, &acc_angle, sample_dt);
+                    gyro = sample.gyro;
+                }
+                true
+            }
+            Err(_) => false,
+        };
+        if let Ok(volts) = battery_adc.read_voltage(&mut adc) {
+            let amps = battery_adc.read_current(&mut adc).ok().flatten();
+            if let Some(event) = battery.update(volts, amps, dt) {
+                let str = format!("Battery {:?}: {:.2} V\r\n", event, volts);
+                serial.write(str.as_bytes()).ok();
+            }
+        }
+        let safety = SafetyInputs {
+            attitude: estimator.angles(),
+            gyro,
+            throttle,
+            sensors_ready: imu_ok && estimator.is_initialized(),
+            imu_healthy: imu_self_test.passed(),
+            link_healthy: usb_dev.state() == UsbDeviceState::Configured,
+            battery_critical: battery.is_critical(),
+        };
+        if let Some(reason) = arming.update(&safety, dt) {
+            throttle = 0.0;
+            let str = format!("Disarmed: {:?}\r\n", reason);
+            serial.write(str.as_bytes()).ok();
+        }
+        motor_manager.advance(dt);
+        if arming.is_armed() {
+            // Controllers only run with thrust so they do not wind up on the ground
+            let demands = if throttle > 0.0 {
+                let setpoint = AttitudeSetpoint {
+                    throttle,
+                    ..AttitudeSetpoint::default()
+                };
+                stabilizer.update(&setpoint, &estimator.angles(), &gyro, dt)
+            } else {
+                stabilizer.reset();
+                AxisDemands::default()
+            };
+            let mut thrusts = mixer.mix(&demands);
+            stabilizer.set_saturated(mixer.is_saturated());
+            thrusts.iter_mut().for_each(|t| *t = battery.compensate(*t));
+            motor_manager.set_thrust_pcts(&thrusts);
+        } else {
+            motor_manager.turn_all_off();
+        }
+
         if usb_dev.poll(&mut [&mut serial]) {
             let mut buf = [0u8; 64];
             match serial.read(&mut buf) {
                 Err(_e) => {}
                 Ok(0) => {}
                 Ok(count) => {
-                    buf.iter_mut().take(count).for_each(|x| match *x as char {
-                        't' => {
-                            let tmp = mpu6050.read_temp().unwrap();
-                            let str = format!("MPU6050 Temp: {:.2}\r\n", tmp);
-                            serial.write(str.as_bytes()).unwrap();
+                    buf.iter_mut().take(count).for_each(|x| {
+                        // Terminals send line endings after a command, which must not
+                        // cancel a pending confirmation
+                        if x.is_ascii_whitespace() {
+                            return;
                         }
-                        'a' => {
-                            let acc = mpu6050.read_acc().unwrap();
-                            let str = format!(
-                                "Planar acceleration: {:.2}, {:.2}, {:.2}\r\n",
-                                acc.x, acc.y, acc.z
-                            );
-                            serial.write(str.as_bytes()).unwrap();
-                        }
-                        'g' => {
-                            let gyro = mpu6050.read_gyro().unwrap();
-                            let str = format!(
-                                "Gyro acceleration: {:.2}, {:.2}, {:.2}\r\n",
-                                gyro.x, gyro.y, gyro.z
-                            );
-                            serial.write(str.as_bytes()).unwrap();
-                        }
-                        'b' => {
-                            serial
-                                .write("Initializing Adafruit 1893...\r\n".as_bytes())
-                                .unwrap();
-                            match a1893.init(&mut delay) {
-                                Ok(_) => serial.write("Adafruit 1893 ok.\r\n".as_bytes()).unwrap(),
-                                Err(Adafruit1893Error::I2c(e)) => serial
+                        let confirming = pending_confirm.take();
+                        match *x as char {
+                            't' => {
+                                let tmp = mpu6050.read_temp().unwrap();
+                                let str = format!("MPU6050 Temp: {:.2}\r\n", tmp);
+                                serial.write(str.as_bytes()).unwrap();
+                            }
+                            'a' => {
+                                let acc = mpu6050.read_acc().unwrap();
+                                let str = format!(
+                                    "Planar acceleration: {:.2}, {:.2}, {:.2}\r\n",
+                                    acc.x, acc.y, acc.z
+                                );
+                                serial.write(str.as_bytes()).unwrap();
+                            }
+                            'g' => {
+                                let gyro = mpu6050.read_gyro().unwrap();
+                                let str = format!(
+                                    "Gyro acceleration: {:.2}, {:.2}, {:.2}\r\n",
+                                    gyro.x, gyro.y, gyro.z
+                                );
+                                serial.write(str.as_bytes()).unwrap();
+                            }
+                            'i' => {
+                                let now = timer.get_counter().ticks();
+                                let sample = mpu6050.read_all(Some(now)).unwrap();
+                                let str = format!(
+                                    "IMU @{}us: acc {:.2}, {:.2}, {:.2} gyro {:.2}, {:.2}, {:.2} temp {:.2}\r\n",
+                                    now,
+                                    sample.acc.x,
+                                    sample.acc.y,
+                                    sample.acc.z,
+                                    sample.gyro.x,
+                                    sample.gyro.y,
+                                    sample.gyro.z,
+                                    sample.temp
+                                );
+                                serial.write(str.as_bytes()).unwrap();
+                            }
+                            'T' => {
+                                for (name, axes) in
+                                    [("Accel", &imu_self_test.accel), ("Gyro", &imu_self_test.gyro)]
+                                {
+                                    let str = format!(
+                                        "{} self-test deviation (%): {:.1}, {:.1}, {:.1} passed: {}\r\n",
+                                        name,
+                                        axes[0].deviation,
+                                        axes[1].deviation,
+                                        axes[2].deviation,
+                                        axes.iter().all(|a| a.passed)
+                                    );
+                                    serial.write(str.as_bytes()).unwrap();
+                                }
+                            }
+                            'e' => {
+                                let angles = estimator.angles();
+                                let str = format!(
+                                    "Attitude (roll, pitch, yaw): {:.2}, {:.2}, {:.2}\r\n",
+                                    angles.x, angles.y, angles.z
+                                );
+                                serial.write(str.as_bytes()).unwrap();
+                            }
+                            'b' => {
+                                serial
+                                    .write("Initializing Adafruit 1893...\r\n".as_bytes())
+                                    .unwrap();
+                                match a1893.init(&mut delay) {
+                                    Ok(_) => {
+                                        serial.write("Adafruit 1893 ok.\r\n".as_bytes()).unwrap()
+                                    }
+                                    Err(Adafruit1893Error::I2c(e)) => serial
+                                        .write(
+                                            format!("Adafruit 1893 I2C error: {:?}\r\n", e)
+                                                .as_bytes(),
+                                        )
+                                        .unwrap(),
+                                    Err(Adafruit1893Error::InvalidChipId(x)) => {
+                                        let str =
+                                            format!("Adafruit 1893 whoami failed: {:#04x}\r\n", x);
+                                        serial.write(str.as_bytes()).unwrap()
+                                    }
+                                    Err(Adafruit1893Error::NoResponse) => serial
+                                        .write("Adafruit 1893 no response\r\n".as_bytes())
+                                        .unwrap(),
+                                };
+                            }
+                            'v' => {
+                                let str = format!(
+                                    "Battery: {:.2} V, {:?} cells, {:.2} A, {:.0} mAh, {:?}\r\n",
+                                    battery.voltage().unwrap_or(0.0),
+                                    battery.cells(),
+                                    battery.current().unwrap_or(0.0),
+                                    battery.consumed_mah(),
+                                    battery.alarm()
+                                );
+                                serial.write(str.as_bytes()).unwrap();
+                            }
+                            'A' => {
+                                let str = match arming.arm(&safety) {
+                                    Ok(_) => "Armed\r\n".into(),
+                                    Err(reason) => format!("Arming refused: {:?}\r\n", reason),
+                                };
+                                serial.write(str.as_bytes()).unwrap();
+                            }
+                            'k' | 'p' if arming.is_armed() => {
+                                serial
+                                    .write("Refused: disarm first\r\n".as_bytes())
+                                    .unwrap();
+                            }
+                            'p' => {
+                                pending_confirm = Some('p');
+                                serial
                                     .write(
-                                        format!("Adafruit 1893 I2C error: {:?}\r\n", e).as_bytes(),
+                                        "Motor test: remove props, then send 'y' to confirm\r\n"
+                                            .as_bytes(),
                                     )
-                                    .unwrap(),
-                                Err(Adafruit1893Error::InvalidChipId(x)) => {
-                                    let str =
-                                        format!("Adafruit 1893 whoami failed: {:#04x}\r\n", x);
-                                    serial.write(str.as_bytes()).unwrap()
+                                    .unwrap();
+                            }
+                            'y' if confirming == Some('p') && !arming.is_armed() => {
+                                for idx in 0..motor_manager.get_motor_count() {
+                                    let str = format!("Spinning M{}\r\n", idx);
+                                    serial.write(str.as_bytes()).unwrap();
+                                    motor_manager.test_motor(idx, 0.05, 2000, &mut delay);
+                                    delay.delay_ms(500);
                                 }
-                                Err(Adafruit1893Error::NoResponse) => serial
-                                    .write("Adafruit 1893 no response\r\n".as_bytes())
-                                    .unwrap(),
-                            };
-                        }
-                        'c' => {
-                            motor_manager.turn_all_off();
-                            serial.write("All motors off\r\n".as_bytes()).unwrap();
-                        }
-                        'm' => {
-                            motor_manager.set_all_thrust_pct(0.05);
-                            serial.write("All motors 5%\r\n".as_bytes()).unwrap();
-                        }
-                        '0' => {
-                            motor_manager.m0().set_thrust_pct(0.05);
-                            serial.write("M0 5%\r\n".as_bytes()).unwrap();
-                        }
-                        '1' => {
-                            motor_manager.m1().set_thrust_pct(0.05);
-                            serial.write("M1 5%\r\n".as_bytes()).unwrap();
-                        }
-                        '2' => {
-                            motor_manager.m2().set_thrust_pct(0.05);
-                            serial.write("M2 5%\r\n".as_bytes()).unwrap();
-                        }
-                        '3' => {
-                            motor_manager.m3().set_thrust_pct(0.05);
-                            serial.write("M3 5%\r\n".as_bytes()).unwrap();
+                                serial.write("Motor test done\r\n".as_bytes()).unwrap();
+                            }
+                            'k' => {
+                                pending_confirm = Some('k');
+                                serial
+                                    .write(
+                                        "ESC calibration: remove props and unplug the battery, \
+                                     then send 'y' to confirm\r\n"
+                                            .as_bytes(),
+                                    )
+                                    .unwrap();
+                            }
+                            'y' if confirming == Some('k') && !arming.is_armed() => {
+                                serial
+                                    .write(
+                                        "Calibrating: plug in the battery within 8 s\r\n"
+                                            .as_bytes(),
+                                    )
+                                    .unwrap();
+                                motor_manager.calibrate(&mut led, &mut delay, &ESC_CALIBRATION);
+                                throttle = 0.0;
+                                serial.write("ESC calibration done\r\n".as_bytes()).unwrap();
+                            }
+                            'd' => {
+                                arming.disarm();
+                                throttle = 0.0;
+                                serial.write("Disarmed\r\n".as_bytes()).unwrap();
+                            }
+                            'c' => {
+                                throttle = 0.0;
+                                serial.write("All motors off\r\n".as_bytes()).unwrap();
+                            }
+                            'm' if !arming.is_armed() => {
+                                serial
+                                    .write("Refused: motors disarmed\r\n".as_bytes())
+                                    .unwrap();
+                            }
+                            'm' => {
+                                throttle = 0.05;
+                                serial.write("Throttle 5%\r\n".as_bytes()).unwrap();
+                            }
+                            _ => {}
                         }
-                        _ => {}
                     });
                 }
             }